@@ -0,0 +1,47 @@
+// Baselines for the hot paths. Every batch and generator is seeded so runs are comparable
+use std::hint::black_box;
+use criterion::{ criterion_group, criterion_main, BatchSize, Criterion };
+use enhancer::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+const ACTORS: i32 = 1000;
+const SEED: u64 = 0;
+
+fn bench_generate_rates(c: &mut Criterion) {
+  let params = default_params();
+  c.bench_function("generate_rates", | b | b.iter(| | generate_rates(black_box(&params))));
+}
+
+fn bench_roll(c: &mut Criterion) {
+  let rates = generate_rates(&default_params()).unwrap();
+  let rate = rates[rates.len() - 2];
+  let mut source = UniformRoll(ChaCha8Rng::seed_from_u64(SEED));
+  c.bench_function("roll", | b | b.iter(| | roll(black_box(rate), &mut source)));
+}
+
+fn bench_run_to_completion(c: &mut Criterion) {
+  let rates = generate_rates(&default_params()).unwrap();
+  c.bench_function("create_many + run_to_completion", | b | b.iter(| | {
+    let mut simulations = EnhancerSimulation::create_many_seeded(&rates, ACTORS, SEED);
+    EnhancerSimulation::run_to_completion(&mut simulations, | _progress | {})
+  }));
+}
+
+// Only the run is timed, each iteration gets a fresh batch
+fn bench_serial_vs_parallel(c: &mut Criterion) {
+  let rates = generate_rates(&default_params()).unwrap();
+  let batch = | | EnhancerSimulation::create_many_seeded(&rates, ACTORS, SEED);
+
+  let mut group = c.benchmark_group("serial vs parallel");
+  group.bench_function("enhance_many", | b | b.iter_batched_ref(batch, | simulations | {
+    while !EnhancerSimulation::enhance_many(simulations) {}
+  }, BatchSize::LargeInput));
+  group.bench_function("run_parallel", | b | b.iter_batched_ref(batch, | simulations | {
+    EnhancerSimulation::run_parallel(simulations)
+  }, BatchSize::LargeInput));
+  group.finish();
+}
+
+criterion_group!(benches, bench_generate_rates, bench_roll, bench_run_to_completion, bench_serial_vs_parallel);
+criterion_main!(benches);
@@ -0,0 +1,439 @@
+// Analytical counterparts to the Monte Carlo simulation. The rates table is treated as an absorbing
+// Markov chain where max level and being destroyed are the absorbing states. Actor-level mechanics
+// such as protection or attempt caps are not modelled, only the rates themselves. The expected
+// visits are those of an actor that survives long enough to make them
+use crate::rate::EnhanceRate;
+
+// Row-stochastic matrix where entry (i, j) is the probability of going from state i to state j in
+// one attempt, (max level + 2) square. States 0 to max level are the levels and the last one is
+// being destroyed, both max level and destroyed are absorbing
+pub fn transition_matrix(rates: &Vec::<EnhanceRate>) -> Vec::<Vec::<f64>> {
+  let size = rates.len();
+  let max_level = size.saturating_sub(1);
+  let destroyed = size;
+  let mut matrix = vec![vec![0.0; size + 1]; size + 1];
+  matrix[destroyed][destroyed] = 1.0;
+
+  for (i, rate) in rates.iter().enumerate() {
+    if i == max_level {
+      matrix[i][i] = 1.0;
+      continue;
+    }
+
+    matrix[i][i + 1] += rate.upgrade as f64;
+    matrix[i][usize::min(max_level, i + 2)] += rate.great_success as f64;
+    matrix[i][i.saturating_sub(1)] += rate.downgrade as f64;
+    // Halves are taken to round down, the default `HalveRounding`
+    matrix[i][i / 2] += rate.halve as f64;
+    matrix[i][rate.reset_level as usize] += rate.reset as f64;
+    matrix[i][destroyed] += rate.destroy as f64;
+    // Summed in f64 rather than taken from `no_change_rate`, so the f32 rounding of the other
+    // outcomes doesn't leave the row slightly off 1
+    let outcomes = [rate.upgrade, rate.great_success, rate.downgrade, rate.halve, rate.reset, rate.destroy];
+    matrix[i][i] += 1.0 - outcomes.iter().map(| &p | p as f64).sum::<f64>();
+  }
+
+  return matrix;
+}
+
+// Solves `a * x = b` with partial pivoting, None if `a` is singular
+fn solve(mut a: Vec::<Vec::<f64>>, mut b: Vec::<f64>) -> Option::<Vec::<f64>> {
+  let n = b.len();
+
+  for column in 0..n {
+    let pivot = (column..n).max_by(| &x, &y | a[x][column].abs().total_cmp(&a[y][column].abs()))?;
+    if a[pivot][column].abs() < 1e-12 {
+      return None;
+    }
+
+    a.swap(column, pivot);
+    b.swap(column, pivot);
+
+    for row in (column + 1)..n {
+      let factor = a[row][column] / a[column][column];
+      for k in column..n {
+        a[row][k] -= factor * a[column][k];
+      }
+      b[row] -= factor * b[column];
+    }
+  }
+
+  let mut x = vec![0.0; n];
+  for row in (0..n).rev() {
+    let mut sum = b[row];
+    for k in (row + 1)..n {
+      sum -= a[row][k] * x[k];
+    }
+    x[row] = sum / a[row][row];
+  }
+
+  return Some(x);
+}
+
+// (I - Q) with Q the transitions among the levels below max, the system every expectation over the chain solves
+fn fundamental_system(rates: &Vec::<EnhanceRate>) -> Vec::<Vec::<f64>> {
+  let transient = rates.len().saturating_sub(1);
+  let matrix = transition_matrix(rates);
+
+  let mut a = vec![vec![0.0; transient]; transient];
+  for i in 0..transient {
+    for j in 0..transient {
+      let identity = if i == j { 1.0 } else { 0.0 };
+      a[i][j] = identity - matrix[i][j];
+    }
+  }
+
+  return a;
+}
+
+// Expected number of attempts made at each level below max by an actor starting at level 0, i.e.
+// row 0 of the fundamental matrix (I - Q)^-1. None if max level can't be reached
+pub fn expected_visits(rates: &Vec::<EnhanceRate>) -> Option::<Vec::<f64>> {
+  let transient = rates.len().saturating_sub(1);
+  let system = fundamental_system(rates);
+
+  // Row 0 of N = (I - Q)^-1 solves (I - Q)^T x = e0
+  let mut a = vec![vec![0.0; transient]; transient];
+  for i in 0..transient {
+    for j in 0..transient {
+      a[j][i] = system[i][j];
+    }
+  }
+
+  let mut b = vec![0.0; transient];
+  if transient > 0 {
+    b[0] = 1.0;
+  }
+
+  return solve(a, b);
+}
+
+// Expected attempts to max starting from each level below max, i.e. (I - Q)^-1 * 1
+pub fn expected_attempts_from(rates: &Vec::<EnhanceRate>) -> Option::<Vec::<f64>> {
+  let transient = rates.len().saturating_sub(1);
+  return solve(fundamental_system(rates), vec![1.0; transient]);
+}
+
+// Variance of the attempts from level 0 to max, from the second moment of the absorption time:
+// Var = ((2N - I) t)_0 - t_0^2 with N the fundamental matrix and t the expected attempts from each
+// level. Infinity if max level can't be reached
+pub fn attempts_variance(rates: &Vec::<EnhanceRate>) -> f64 {
+  let (Some(visits), Some(from)) = (expected_visits(rates), expected_attempts_from(rates)) else {
+    return f64::INFINITY;
+  };
+
+  let Some(&mean) = from.first() else {
+    return 0.0;
+  };
+
+  let second_moment = 2.0 * visits.iter().zip(from.iter()).map(| (n, t) | n * t).sum::<f64>() - mean;
+  return f64::max(0.0, second_moment - mean * mean);
+}
+
+// One number for how painful a curve is to grind through, for ranking candidates:
+//   log10(1 + mean) * (1 + cv)
+// where mean is the expected attempts to max and cv its coefficient of variation. The log keeps
+// curves of very different lengths comparable, a score of 3 is roughly "a thousand attempts with
+// no spread", and the cv term scales it up for curves where some actors get a far worse run than
+// others. Infinity if max level can't be reached
+pub fn grind_score(rates: &Vec::<EnhanceRate>) -> f64 {
+  let mean = expected_attempts(rates);
+  if !mean.is_finite() {
+    return f64::INFINITY;
+  }
+
+  let cv = if mean > 0.0 { attempts_variance(rates).sqrt() / mean } else { 0.0 };
+  return (1.0 + mean).log10() * (1.0 + cv);
+}
+
+// Expected total attempts from level 0 to max, infinity if max level can't be reached
+pub fn expected_attempts(rates: &Vec::<EnhanceRate>) -> f64 {
+  return match expected_visits(rates) {
+    Some(visits) => visits.iter().sum(),
+    None => f64::INFINITY
+  };
+}
+
+// Chance that an actor starting at level 0 has its item destroyed before reaching max, i.e. the
+// absorption probability of the destroyed state. Each attempt at level i destroys with its destroy
+// rate, so this is the expected visits weighted by those rates. 0 without any destroy chance, NaN if
+// actors can end up stuck at levels they can neither leave towards max nor be destroyed at
+pub fn destruction_probability(rates: &Vec::<EnhanceRate>) -> f64 {
+  let transient = rates.len().saturating_sub(1);
+  if rates[..transient].iter().all(| rate | rate.destroy == 0.0) {
+    return 0.0;
+  }
+
+  return match expected_visits(rates) {
+    Some(visits) => visits.iter().zip(rates.iter()).map(| (n, rate) | n * rate.destroy as f64).sum(),
+    None => f64::NAN
+  };
+}
+
+// Levels ranked by the expected number of attempts an actor spends at them on the way to max,
+// worst wall first. Downgrade churn is included, a level that keeps throwing actors back is
+// revisited and counted each time. If max level is unreachable every level reports infinity
+pub fn bottleneck_levels(rates: &Vec::<EnhanceRate>) -> Vec::<(i32, f64)> {
+  let transient = rates.len().saturating_sub(1);
+  let visits = expected_visits(rates).unwrap_or_else(|| vec![f64::INFINITY; transient]);
+
+  let mut output = visits.into_iter()
+    .enumerate()
+    .map(| (level, attempts) | (level as i32, attempts))
+    .collect::<Vec::<(i32, f64)>>();
+  output.sort_by(| a, b | b.1.total_cmp(&a.1));
+
+  return output;
+}
+
+// Levels below max whose chance to advance (upgrade or great success) is less than `ratio_threshold`
+// times the previous level's, i.e. sudden walls rather than the steady decline of a curve. Unlike
+// `bottleneck_levels` this looks at the jump between neighbours, not at how hard a level is overall
+pub fn detect_cliffs(rates: &Vec::<EnhanceRate>, ratio_threshold: f32) -> Vec::<i32> {
+  let transient = rates.len().saturating_sub(1);
+  let mut output = Vec::<i32>::new();
+
+  for i in 1..transient {
+    let previous = rates[i - 1].advance_rate();
+    if previous > 0.0 && rates[i].advance_rate() < ratio_threshold * previous {
+      output.push(rates[i].level);
+    }
+  }
+
+  return output;
+}
+
+// Expected currency spent to first reach each level, index 0 being the free starting level.
+// `costs` is indexed by level like `EnhancerParams::cost_per_attempt`, levels past its end are free.
+// Reaching level L only depends on the chain up to L, so each level is solved with max moved to L
+pub fn expected_cost_by_level(rates: &Vec::<EnhanceRate>, costs: &Vec::<f32>) -> Vec::<f64> {
+  let mut output = vec![0.0];
+
+  for target in 1..rates.len() {
+    let truncated = rates[..=target].to_vec();
+    let cost = match expected_visits(&truncated) {
+      Some(visits) => visits.iter()
+        .enumerate()
+        .map(| (level, attempts) | attempts * costs.get(level).copied().unwrap_or(0.0) as f64)
+        .sum(),
+      None => f64::INFINITY
+    };
+    output.push(cost);
+  }
+
+  return output;
+}
+
+// Lowest level whose expected cost to reach is strictly lower under model b than under model a,
+// None if b never becomes cheaper. Only levels present in both rates tables are compared
+pub fn cost_crossover(rates_a: &Vec::<EnhanceRate>, costs_a: &Vec::<f32>, rates_b: &Vec::<EnhanceRate>, costs_b: &Vec::<f32>) -> Option::<i32> {
+  let expected_a = expected_cost_by_level(rates_a, costs_a);
+  let expected_b = expected_cost_by_level(rates_b, costs_b);
+
+  return expected_a.iter()
+    .zip(expected_b.iter())
+    .position(| (a, b) | b < a)
+    .map(| level | level as i32);
+}
+
+// Level to stop at when every level is worth its `value` and attempts cost `costs`: the highest
+// level whose step up from the one below still adds more value than the extra expected cost of
+// reaching it. Every step is checked, so with uneven costs or values a step that pays again after
+// one that doesn't still counts. 0 if no step pays
+pub fn optimal_stop_level(rates: &Vec::<EnhanceRate>, costs: &Vec::<f32>) -> i32 {
+  let expected_costs = expected_cost_by_level(rates, costs);
+  let mut stop = 0;
+
+  for level in 0..rates.len().saturating_sub(1) {
+    let value_gain = (rates[level + 1].value - rates[level].value) as f64;
+    let marginal_cost = expected_costs[level + 1] - expected_costs[level];
+
+    if value_gain > marginal_cost {
+      stop = level as i32 + 1;
+    }
+  }
+
+  return stop;
+}
+
+// Quasi-stationary distribution over the levels below max: where an actor that has been grinding
+// for a long time and still hasn't reached max is likely to be, i.e. the level distribution
+// conditional on not yet being absorbed. It is the dominant left eigenvector of the sub-stochastic
+// matrix Q, found by power iteration on the lazy chain (Q + I) / 2, which has the same eigenvector
+// but can't oscillate. Sums to 1, empty if there are no levels below max
+pub fn steady_state_distribution(rates: &Vec::<EnhanceRate>) -> Vec::<f64> {
+  const MAX_ITERATIONS: i32 = 1_000_000;
+  const TOLERANCE: f64 = 1e-13;
+
+  let transient = rates.len().saturating_sub(1);
+  let matrix = transition_matrix(rates);
+  let mut distribution = vec![1.0 / transient as f64; transient];
+
+  for _iteration in 0..MAX_ITERATIONS {
+    let mut next = vec![0.0; transient];
+    for i in 0..transient {
+      for j in 0..transient {
+        let lazy = if i == j { 1.0 + matrix[i][j] } else { matrix[i][j] };
+        next[j] += distribution[i] * lazy / 2.0;
+      }
+    }
+
+    let total = next.iter().sum::<f64>();
+    if total <= 0.0 {
+      break;
+    }
+    for p in next.iter_mut() {
+      *p /= total;
+    }
+
+    let change = next.iter().zip(distribution.iter()).map(| (a, b) | (a - b).abs()).sum::<f64>();
+    distribution = next;
+    if change < TOLERANCE {
+      break;
+    }
+  }
+
+  return distribution;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::params::{ default_params, generate_rates, EnhanceRateOverride, EnhancerParams };
+  use crate::testing::completed_batch;
+
+  #[test]
+  fn hardest_level_is_the_top_bottleneck() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let lowest_upgrade = rates[..rates.len() - 1].iter().map(| rate | rate.advance_rate()).fold(f32::INFINITY, f32::min);
+
+    let (top_level, _attempts) = bottleneck_levels(&rates)[0];
+    assert_eq!(rates[top_level as usize].advance_rate(), lowest_upgrade);
+  }
+
+  #[test]
+  fn crossover_finds_where_the_late_model_gets_cheaper() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let cheap_early = vec![1.0, 1.0, 1.0, 1.0, 20.0, 20.0, 20.0, 20.0, 20.0, 20.0];
+    let cheap_late = vec![5.0; 10];
+
+    let crossover = cost_crossover(&rates, &cheap_early, &rates, &cheap_late).unwrap();
+    let expected_early = expected_cost_by_level(&rates, &cheap_early);
+    let expected_late = expected_cost_by_level(&rates, &cheap_late);
+
+    assert!(crossover > 1);
+    assert!(expected_late[crossover as usize] < expected_early[crossover as usize]);
+    for level in 1..crossover as usize {
+      assert!(expected_late[level] >= expected_early[level]);
+    }
+    assert_eq!(cost_crossover(&rates, &cheap_late, &rates, &cheap_late), None);
+  }
+
+  #[test]
+  fn steep_costs_stop_well_below_max() {
+    let params = EnhancerParams { value_increment: 50.0, ..default_params() };
+    let rates = generate_rates(&params).unwrap();
+    let costs = (0..10).map(| level | 2f32.powi(level)).collect::<Vec::<f32>>();
+
+    let stop = optimal_stop_level(&rates, &costs);
+    assert!(stop > 0 && stop < params.max_level - 3, "stopped at {stop}");
+
+    let expected_costs = expected_cost_by_level(&rates, &costs);
+    let worth_it = | level: usize | (rates[level + 1].value - rates[level].value) as f64 > expected_costs[level + 1] - expected_costs[level];
+    assert!(worth_it(stop as usize - 1));
+    assert!((stop as usize..params.max_level as usize).all(| level | !worth_it(level)));
+  }
+
+  #[test]
+  fn a_pricey_level_in_the_middle_doesnt_stop_the_climb() {
+    // Without downgrades nobody pays for level 2 twice, so the steps past it are cheap again
+    let params = EnhancerParams { value_increment: 50.0, max_downgrade_rate: 0.0, ..default_params() };
+    let rates = generate_rates(&params).unwrap();
+    let mut costs = vec![1.0; 10];
+    costs[2] = 1_000.0;
+
+    let expected_costs = expected_cost_by_level(&rates, &costs);
+    let worth_it = | level: usize | (rates[level + 1].value - rates[level].value) as f64 > expected_costs[level + 1] - expected_costs[level];
+    assert!(!worth_it(2));
+    assert!((3..params.max_level as usize).all(worth_it));
+
+    // The first step that doesn't pay would have stopped at 2
+    assert_eq!(optimal_stop_level(&rates, &costs), params.max_level);
+  }
+
+  #[test]
+  fn grind_score_rises_with_downgrade_rates() {
+    let mut previous = 0.0;
+    for max_downgrade_rate in [0.0, 0.1, 0.2, 0.3, 0.4, 0.5] {
+      let rates = generate_rates(&EnhancerParams { max_downgrade_rate, ..default_params() }).unwrap();
+      let score = grind_score(&rates);
+      assert!(score > previous, "score {score} at downgrade rate {max_downgrade_rate} is not above {previous}");
+      previous = score;
+    }
+  }
+
+  #[test]
+  fn steady_state_sums_to_one_and_piles_up_at_the_hardest_level() {
+    let mut params = default_params();
+    // Level 6 is by far the hardest to get past
+    params.overrides.insert(6, EnhanceRateOverride { upgrade: Some(0.01), great_success: Some(0.0), ..EnhanceRateOverride::default() });
+    let rates = generate_rates(&params).unwrap();
+    let distribution = steady_state_distribution(&rates);
+
+    assert_eq!(distribution.len(), rates.len() - 1);
+    assert!((distribution.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    assert!(distribution.iter().all(| &p | p >= 0.0));
+
+    let mode = (0..distribution.len()).max_by(| &a, &b | distribution[a].total_cmp(&distribution[b])).unwrap();
+    assert_eq!(mode, 6);
+  }
+
+  #[test]
+  fn destruction_probability_matches_the_destroyed_fraction() {
+    let mut params = default_params();
+    params.destroy_ratio = 0.05;
+    let rates = generate_rates(&params).unwrap();
+
+    let simulations = completed_batch(&rates, 5_000, 1);
+    let destroyed = simulations.iter().filter(| sim | sim.destroyed).count() as f64 / simulations.len() as f64;
+
+    let analytical = destruction_probability(&rates);
+    assert!(analytical > 0.1 && analytical < 0.9);
+    assert!((destroyed - analytical).abs() < 0.03, "simulated {destroyed}, analytical {analytical}");
+  }
+
+  #[test]
+  fn every_transition_row_sums_to_one() {
+    let mut params = default_params();
+    params.destroy_ratio = 0.2;
+    let rates = generate_rates(&params).unwrap();
+    let matrix = transition_matrix(&rates);
+    let max_level = rates.len() - 1;
+    let destroyed = rates.len();
+
+    assert_eq!(matrix.len(), rates.len() + 1);
+    for row in matrix.iter() {
+      assert_eq!(row.len(), matrix.len());
+      assert!((row.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+      assert!(row.iter().all(| &p | p >= 0.0));
+    }
+    assert!(rates[max_level - 1].destroy > 0.0);
+    assert_eq!(matrix[max_level - 1][destroyed], rates[max_level - 1].destroy as f64);
+
+    assert_eq!(matrix[max_level][max_level], 1.0);
+    assert_eq!(matrix[destroyed][destroyed], 1.0);
+  }
+
+  #[test]
+  fn a_sharply_harder_level_is_a_cliff() {
+    let default_rates = generate_rates(&default_params()).unwrap();
+    assert!(detect_cliffs(&default_rates, 0.4).is_empty());
+
+    let mut params = default_params();
+    let curve_upgrade = default_rates[6].upgrade;
+    params.overrides.insert(6, EnhanceRateOverride { upgrade: Some(curve_upgrade * 0.25), ..EnhanceRateOverride::default() });
+    let rates = generate_rates(&params).unwrap();
+
+    assert_eq!(detect_cliffs(&rates, 0.4), vec![6]);
+  }
+}
@@ -0,0 +1,164 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{ self, BufReader, BufWriter };
+use std::path::Path;
+use serde::{ Deserialize, Serialize };
+use crate::rate::EnhanceRate;
+use crate::simulation::EnhancerSimulation;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+  Io(io::Error),
+  Format(serde_json::Error),
+  // The checkpoint was taken with a different rates table than the one it is being resumed with
+  MaxLevelMismatch { saved: i32, provided: i32 }
+}
+
+impl fmt::Display for CheckpointError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CheckpointError::Io(error) => write!(f, "checkpoint io error: {error}"),
+      CheckpointError::Format(error) => write!(f, "malformed checkpoint: {error}"),
+      CheckpointError::MaxLevelMismatch { saved, provided } => {
+        write!(f, "checkpoint was saved with max level {saved} but the provided rates have max level {provided}")
+      }
+    }
+  }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+  fn from(error: io::Error) -> Self {
+    return CheckpointError::Io(error);
+  }
+}
+
+impl From<serde_json::Error> for CheckpointError {
+  fn from(error: serde_json::Error) -> Self {
+    return CheckpointError::Format(error);
+  }
+}
+
+#[derive(Serialize)]
+struct CheckpointRef<'s, 'a> {
+  max_level: i32,
+  simulations: &'s Vec::<EnhancerSimulation<'a>>
+}
+
+#[derive(Deserialize)]
+struct Checkpoint<'a> {
+  max_level: i32,
+  simulations: Vec::<EnhancerSimulation<'a>>
+}
+
+fn max_level_of(rates: &Vec::<EnhanceRate>) -> i32 {
+  return rates.len() as i32 - 1;
+}
+
+// Writes the full actor state, including each actor's generator, so a resumed run continues
+// exactly where this one left off
+pub fn save_checkpoint(simulations: &Vec::<EnhancerSimulation>, path: &Path) -> Result<(), CheckpointError> {
+  let max_level = simulations.first().map_or(0, | sim | max_level_of(sim.rates));
+  let checkpoint = CheckpointRef { max_level, simulations };
+
+  let writer = BufWriter::new(File::create(path)?);
+  serde_json::to_writer(writer, &checkpoint)?;
+
+  return Ok(());
+}
+
+// Loads a checkpoint and re-associates every actor with `rates`, which must have been generated
+// with the same max level as the saved run
+pub fn load_checkpoint<'a>(path: &Path, rates: &'a Vec::<EnhanceRate>) -> Result<Vec::<EnhancerSimulation<'a>>, CheckpointError> {
+  let reader = BufReader::new(File::open(path)?);
+  let checkpoint: Checkpoint = serde_json::from_reader(reader)?;
+
+  let provided = max_level_of(rates);
+  if checkpoint.max_level != provided && !checkpoint.simulations.is_empty() {
+    return Err(CheckpointError::MaxLevelMismatch { saved: checkpoint.max_level, provided });
+  }
+
+  let mut simulations = checkpoint.simulations;
+  for sim in &mut simulations {
+    sim.rates = rates;
+    sim.cost_by_level.resize(rates.len(), 0.0);
+  }
+
+  return Ok(simulations);
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env;
+  use std::fs;
+  use std::process;
+  use super::*;
+  use crate::params::{ default_params, generate_rates, EnhancerParams };
+  use crate::testing::completed_batch;
+
+  #[test]
+  fn resumed_run_matches_uninterrupted_run() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let path = env::temp_dir().join(format!("enhancer-checkpoint-{}.json", process::id()));
+
+    let mut uninterrupted = EnhancerSimulation::create_many_seeded(&rates, 200, 7);
+    EnhancerSimulation::run_to_completion(&mut uninterrupted, | _progress | {});
+
+    let mut interrupted = EnhancerSimulation::create_many_seeded(&rates, 200, 7);
+    for _iteration in 0..100 {
+      EnhancerSimulation::enhance_many(&mut interrupted);
+    }
+    save_checkpoint(&interrupted, &path).unwrap();
+    let mut resumed = load_checkpoint(&path, &rates).unwrap();
+    fs::remove_file(&path).unwrap();
+    EnhancerSimulation::run_to_completion(&mut resumed, | _progress | {});
+
+    assert_eq!(resumed.len(), uninterrupted.len());
+    for (a, b) in resumed.iter().zip(uninterrupted.iter()) {
+      assert_eq!(a.level, b.level);
+      assert_eq!(a.attempt_count, b.attempt_count);
+      assert_eq!(a.history, b.history);
+    }
+  }
+
+  // Fields added to `EnhancerSimulation` since checkpoints were introduced
+  const NEWER_FIELDS: [&str; 8] = [
+    "rules", "reached_protected", "materials_used", "abandoned", "total_cost", "refunded", "cost_by_level", "destroyed"
+  ];
+
+  #[test]
+  fn checkpoint_without_newer_fields_still_loads() {
+    // Costs, so resuming without a per-level cost table would index past its end
+    let params = EnhancerParams { cost_per_attempt: vec![1.0; 10], ..default_params() };
+    let rates = generate_rates(&params).unwrap();
+    let path = env::temp_dir().join(format!("enhancer-old-checkpoint-{}.json", process::id()));
+
+    let uninterrupted = completed_batch(&rates, 50, 7);
+
+    let mut interrupted = EnhancerSimulation::create_many_seeded(&rates, 50, 7);
+    for _iteration in 0..20 {
+      EnhancerSimulation::enhance_many(&mut interrupted);
+    }
+    save_checkpoint(&interrupted, &path).unwrap();
+
+    // Rewrite it the way an older version would have saved it
+    let mut json = serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&path).unwrap()).unwrap();
+    for simulation in json["simulations"].as_array_mut().unwrap() {
+      for field in NEWER_FIELDS {
+        simulation.as_object_mut().unwrap().remove(field);
+      }
+    }
+    fs::write(&path, json.to_string()).unwrap();
+
+    let mut resumed = load_checkpoint(&path, &rates).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(resumed.iter().all(| sim | sim.reached_protected));
+    EnhancerSimulation::run_to_completion(&mut resumed, | _progress | {});
+
+    for (a, b) in resumed.iter().zip(uninterrupted.iter()) {
+      assert_eq!(a.attempt_count, b.attempt_count);
+      assert_eq!(a.history, b.history);
+    }
+  }
+}
@@ -0,0 +1,52 @@
+// The repo favours explicit `return`s, index loops and `&Vec` parameters
+#![allow(clippy::needless_return, clippy::needless_range_loop, clippy::ptr_arg)]
+
+mod analysis;
+mod checkpoint;
+mod multi_item;
+mod params;
+#[cfg(feature = "plotting")]
+mod plot;
+mod rate;
+mod report;
+mod simulation;
+mod stats;
+mod streaming;
+mod sweep;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use analysis::*;
+pub use checkpoint::*;
+pub use multi_item::*;
+pub use params::*;
+#[cfg(feature = "plotting")]
+pub use plot::*;
+pub use rate::*;
+pub use report::*;
+pub use simulation::*;
+pub use stats::*;
+pub use streaming::*;
+pub use sweep::*;
+
+// Goes through the crate root like a dependent would, and runs with or without the plotting feature
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn full_run_and_statistics_without_plotting() {
+    let params = default_params();
+    let rates = generate_rates(&params).unwrap();
+    let mut simulations = EnhancerSimulation::create_many_seeded(&rates, 100, 1);
+    EnhancerSimulation::run_to_completion(&mut simulations, | _progress | {});
+
+    let stats = level_statistics(&simulations);
+    assert_eq!(stats.len(), rates.len());
+    assert!(mean_attempts(&simulations).is_finite());
+
+    let report = Report::build(&params, &rates, &simulations, Some(1));
+    assert_eq!(report.completed, 100);
+    assert!(preview(&params).unwrap().contains("Expected attempts to max"));
+  }
+}
@@ -1,428 +1,257 @@
-use plotlib::page::Page;
-use plotlib::repr::{ BoxPlot, Plot };
-use plotlib::view::{ CategoricalView, ContinuousView };
-use plotlib::style::{ BoxStyle, PointMarker, PointStyle };
+use std::io::{ self, IsTerminal };
+use std::path::PathBuf;
+use std::process;
+use clap::Parser;
+use enhancer::*;
+use log::{ debug, info, warn };
 use rand::prelude::*;
 
-#[derive(Clone, Copy, Debug)]
-struct EnhancerParams {
-  pub max_level: i32,
-
-  // At level 0, value == min_value
-  // For each level after 1, value += value_increment
-  pub value_increment: f32,
-  pub min_value: f32,
-
-  // At level 0, upgrade rate == max_upgrade_rate
-  // For each level after 1, upgrade_rate *= upgrade_rate_curve
-  pub upgrade_rate_curve: f32,
-  pub max_upgrade_rate: f32,
-  pub min_upgrade_rate: f32,
-
-  // At level max, downgrade_rate == max_downgrade_rate
-  // For each level below max, downgrade_rate *= downgrade_rate_curve
-  pub downgrade_rate_curve: f32,
-  pub max_downgrade_rate: f32,
-  pub halve_ratio: f32,
-  pub reset_ratio: f32,
-  pub min_downgrade_level: i32,
-  pub min_halve_level: i32,
-  pub min_reset_level: i32
+// Safety net against curves that are technically reachable but would take forever
+const MAX_ITERATIONS: i32 = 1_000_000;
+
+/// Simulates an elsword-esque equipment enhance process and plots how many attempts it takes to
+/// reach each level
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+  /// Number of actors to simulate
+  #[arg(long, default_value_t = 10000, value_parser = clap::value_parser!(i32).range(1..))]
+  actors: i32,
+
+  /// Stop each actor once it reaches this level instead of the configured max level
+  #[arg(long)]
+  target_level: Option<i32>,
+
+  /// Seed for the random number generators, makes a run reproducible
+  #[arg(long)]
+  seed: Option<u64>,
+
+  /// TOML file overriding fields of the default `EnhancerParams`
+  #[arg(long)]
+  config: Option<PathBuf>,
+
+  /// Read `EnhancerParams` as JSON from stdin, fields left out keep their defaults
+  #[arg(long, conflicts_with = "config")]
+  params_stdin: bool,
+
+  /// Where to write the scatter plot
+  #[arg(long, default_value = "scatter.svg")]
+  scatter_out: PathBuf,
+
+  /// Where to write the box plot
+  #[arg(long, default_value = "box.svg")]
+  box_out: PathBuf,
+
+  /// Also write the scatter plot, box plot and attempts histogram together into one SVG
+  #[arg(long)]
+  dashboard_out: Option<PathBuf>,
+
+  /// Plot attempts on a log scale in the scatter and box plots
+  #[arg(long)]
+  log_y: bool,
+
+  /// Write the params, rates and every statistic of the run as one JSON file
+  #[arg(long)]
+  report_out: Option<PathBuf>,
+
+  /// Print the rates table and the analytical expectations, then exit without simulating
+  #[arg(long)]
+  dry_run: bool
 }
 
-#[derive(Clone, Copy, Debug)]
-struct EnhanceRate {
-  pub level: i32,
-  pub value: f32,
-  pub upgrade: f32,
-  pub downgrade: f32,
-  pub halve: f32,
-  pub reset: f32,
+fn exit_with_error(message: String) -> ! {
+  eprintln!("{message}");
+  process::exit(1);
 }
 
-impl EnhanceRate {
-  const FORMAT_PRECISION: usize = 1;
-  const FORMAT_COLUMN_WIDTH: usize = (1 + 5);
-  const FORMAT_LEVEL_WIDTH: usize = 3;
-  const FORMAT_SEPARATOR_HEAD: &'static str = " | ";
-  const FORMAT_SEPARATOR_BODY: &'static str = " | ";
-
-  pub fn no_change_rate(&self) -> f32 {
-    return 1.0 - (self.reset + self.halve + self.downgrade + self.upgrade);
-  }
-  
-  pub fn format_table(rates: &Vec::<EnhanceRate>) -> String {
-    let heading = Self::format_table_heading();
-    let rows = rates.iter()
-      .map(| rate | Self::format_table_row(rate))
-      .collect::<Vec::<String>>()
-      .concat();
-
-    return format!("{heading}{rows}");
-  }
-
-  pub fn format_table_heading() -> String {
-    let level = format!("{:<1$}", "LVL", Self::FORMAT_LEVEL_WIDTH);
-    let value = format!("{:<1$}", "VALUE", Self::FORMAT_COLUMN_WIDTH);
-    let upgrade = format!("{:<1$}", "GAIN", Self::FORMAT_COLUMN_WIDTH);
-    let no_change = format!("{:<1$}", "NONE", Self::FORMAT_COLUMN_WIDTH);
-    let downgrade = format!("{:<1$}", "LOSE", Self::FORMAT_COLUMN_WIDTH);
-    let halve = format!("{:<1$}", "HALVE", Self::FORMAT_COLUMN_WIDTH);
-    let reset = format!("{:<1$}", "RESET", Self::FORMAT_COLUMN_WIDTH);
-
-    return format!("{1}{0}{2}{0}{3}{0}{4}{0}{5}{0}{6}{0}{7}\n", Self::FORMAT_SEPARATOR_HEAD, level, value, upgrade, no_change, downgrade, halve, reset);
-  }
-
-  pub fn format_table_row(rate: &EnhanceRate) -> String {
-    let level = format!("{:>1$}", rate.level, Self::FORMAT_LEVEL_WIDTH);
-    let value = Self::format_rate_ex(rate.value * 100.0, 1, Self::FORMAT_COLUMN_WIDTH);
-    let upgrade = Self::format_rate(rate.upgrade);
-    let no_change = Self::format_rate(rate.no_change_rate());
-    let downgrade = Self::format_rate(rate.downgrade);
-    let halve = Self::format_rate(rate.halve);
-    let reset = Self::format_rate(rate.reset);
-
-    return format!("{1}{0}{2}{0}{3}{0}{4}{0}{5}{0}{6}{0}{7}\n", Self::FORMAT_SEPARATOR_BODY, level, value, upgrade, no_change, downgrade, halve, reset);
-  }
-
-  pub fn format_rate(rate: f32) -> String {
-    return Self::format_rate_ex(rate * 100.0, Self::FORMAT_PRECISION, Self::FORMAT_COLUMN_WIDTH);
-  }
-
-  pub fn format_rate_ex(rate: f32, precision: usize, max_width: usize) -> String {
-    let number = format!("{rate:.precision$}%");
-    return format!("{number:>max_width$}");
-  }
-}
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum EnhanceResult {
-  NoChange,
-  Upgrade,
-  Downgrade,
-  Halve,
-  Reset
-}
-
-#[derive(Clone, Debug)]
-struct EnhancerSimulation<'a> {
-  pub level: i32,
-  pub attempt_count: i32,
-  pub rates: &'a Vec::<EnhanceRate>,
-  pub history: Vec::<i32>
-}
-
-impl EnhancerSimulation<'_> {
-  pub fn boxplot_data(simulations: &Vec::<EnhancerSimulation>) -> Vec::<Vec::<f64>> {
-    let mut output = Vec::<Vec::<f64>>::new();
-
-    for sim in simulations {
-      let history = &sim.history;
-
-      for i in 0..history.len() {
-        if i == output.len() {
-          output.push(Vec::<f64>::new());
-        }
-
-        output[i].push(history[i] as f64);
-      }
-    }
-
-    return output;
-  }
-
-  pub fn scatterplot_data(simulations: &Vec::<EnhancerSimulation>) -> Vec::<(f64, f64)> {
-    let mut output = Vec::<(f64, f64)>::new();
-    for sim in simulations {
-      let history = &sim.history;
-      for i in 0..history.len() {
-        let x = i as f64;
-        let y = history[i] as f64;
-
-        output.push((x, y));
-      }
+fn main() {
+  env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+  let args = Args::parse();
+
+  let params = if args.params_stdin {
+    read_params_json(io::stdin().lock()).unwrap_or_else(| error | exit_with_error(format!("stdin: {error}")))
+  } else {
+    match &args.config {
+      Some(path) => load_params(path).unwrap_or_else(| error | exit_with_error(format!("{}: {error}", path.display()))),
+      None => default_params()
     }
+  };
 
-    return output;
-  }
-
-  pub fn create_many(rates: &Vec::<EnhanceRate>, count: i32) -> Vec::<EnhancerSimulation> {
-    let mut output = Vec::<EnhancerSimulation>::with_capacity(count as usize);
-
-    for _i in 0..count {
-      output.push(Self::create(rates));
+  let mut rates = generate_rates(&params).unwrap_or_else(| error | exit_with_error(format!("Invalid params: {error}")));
+  if let Some(target_level) = args.target_level {
+    if target_level < 1 || target_level > params.max_level {
+      exit_with_error(format!("Target level must be between 1 and {}, got {target_level}", params.max_level));
     }
 
-    return output;
+    rates.truncate(target_level as usize + 1);
   }
+  let rates = rates;
 
-  pub fn create(rates: &Vec::<EnhanceRate>) -> EnhancerSimulation {
-    let level = 0;
-    let count = 0;
-    let mut history = Vec::<i32>::new();
-    history.push(0);
-    return EnhancerSimulation { level, attempt_count: count, rates, history };
+  if args.dry_run {
+    println!("{}", preview_rates(&rates));
+    return;
   }
 
-  // Returns true if the set has reached max level
-  pub fn enhance_many(simulations: &mut Vec::<EnhancerSimulation>) -> bool {
-    let mut all_maxed = true;
-
-    for sim in simulations {
-      if !sim.enhance() {
-        all_maxed = false;
-      }
+  // Picked here rather than left to `create_many` so the report can record it
+  let seed = args.seed.unwrap_or_else(| | thread_rng().gen());
+  let mut simulations = EnhancerSimulation::create_many_seeded(&rates, args.actors, seed);
+  EnhancerSimulation::set_rules_many(&mut simulations, EnhanceRules::from_params(&params));
+
+  // Log output goes to stderr, so that's the stream that decides on colours
+  let table_format = TableFormat { colorize: io::stderr().is_terminal(), ..TableFormat::default() };
+  let rates_table = EnhanceRate::format_table_with(&rates, &table_format);
+  info!("Computed enhancement rates:\n{rates_table}");
+
+  // With an attempt cap the run terminates either way, stuck actors just end up abandoned
+  if let Err(error) = rates_reachable(&rates) {
+    match params.attempt_cap {
+      Some(_) => warn!("{error}"),
+      None => exit_with_error(format!("Invalid enhancement rates: {error}"))
     }
-
-    return all_maxed;
   }
-  
-  // Returns true if it has reached max level
-  pub fn enhance(&mut self) -> bool {
-    let i = self.level as usize;
-    if i >= (self.rates.len() - 1) {
-      return true;
-    }
-
-    let rate = self.rates[i];
-    let result = roll(rate);
-    let level = apply_result(self.level, result);
-    let attempt_count = self.attempt_count + 1;
 
-    self.level = level;
-    self.attempt_count = attempt_count;
-
-    if level as usize == self.history.len() {
-      self.history.push(attempt_count);
+  info!("Starting simulation of {} actors", simulations.len());
+  let result = EnhancerSimulation::run_capped(&mut simulations, MAX_ITERATIONS, | progress | {
+    if progress.iterations % 2500 == 0 {
+      debug!(
+        "Reached {} iterations, mean attempts to max {:.1} over {} completed actors",
+        progress.iterations, progress.running_mean, progress.completed_count
+      );
     }
-
-    return false;
+  });
+  let iterations = result.unwrap_or_else(| error | exit_with_error(format!("Simulation failed: {error}")));
+  info!("Simulation complete at {iterations} iterations");
+  info!("Attempts to max: mean {:.1}, gini {:.3}", mean_attempts(&simulations), attempt_gini(&simulations));
+
+  if let Some(attempt_cap) = params.attempt_cap {
+    let abandoned = abandon_statistics(&simulations);
+    info!("Abandoned after {attempt_cap} attempts: {} actors ({:.1}%)", abandoned.count, abandoned.fraction * 100.0);
   }
-}
 
-fn gen_value(params: EnhancerParams, level: i32) -> f32 {
-  let mut value = params.min_value;
-  for _i in 0..level {
-    value += params.value_increment;
+  if params.destroy_ratio > 0.0 {
+    let destroyed = destroy_statistics(&simulations);
+    info!("Destroyed: {} actors ({:.1}%)", destroyed.count, destroyed.fraction * 100.0);
   }
 
-  return value;
-}
-
-fn gen_upgrade_rate(params: EnhancerParams, level: i32) -> f32 {
-  if level >= params.max_level {
-    return 0.0;
+  if !params.cost_per_attempt.is_empty() {
+    let cost = cost_statistics(&simulations);
+    info!(
+      "Cost: mean spent {:.1}, mean refunded {:.1}, net mean {:.1}, net median {:.1}, net p95 {:.1}",
+      cost.mean_spent, cost.mean_refunded, cost.net_mean, cost.net_median, cost.net_p95
+    );
   }
 
-  let mut upgrade_rate = params.max_upgrade_rate;
-  for _i in 0..level {
-    upgrade_rate = f32::max(params.min_upgrade_rate, upgrade_rate * params.upgrade_rate_curve);
-  }
-
-  return upgrade_rate;
-}
-
-fn gen_downgrade_rate(params: EnhancerParams, level: i32) -> f32 {
-  if level >= params.max_level {
-    return 0.0;
+  if params.protection_charges > 0 {
+    let charges = charge_statistics(&simulations);
+    info!(
+      "Protection charges: mean used {:.1} of {}, {:.1}% of actors ran out",
+      charges.mean_used, params.protection_charges, charges.exhausted_fraction * 100.0
+    );
   }
 
-  if level < params.min_downgrade_level {
-    return 0.0;
+  if params.seconds_per_attempt > 0.0 || !params.seconds_per_attempt_by_level.is_empty() {
+    let time = time_statistics(&simulations);
+    info!(
+      "Time to max: mean {}, median {}, p95 {}",
+      format_duration(time.mean), format_duration(time.median), format_duration(time.p95)
+    );
   }
 
-  let mut downgrade_rate = params.max_downgrade_rate;
-  let count = params.max_level - level - 1;
-  for _i in 0..count {
-    downgrade_rate *= params.downgrade_rate_curve;
+  if !params.materials_per_attempt.is_empty() {
+    let materials = material_statistics(&simulations);
+    info!("Materials used: mean {:.1}, median {:.1}, p95 {:.1}", materials.mean, materials.median, materials.p95);
   }
 
-  return downgrade_rate;
-}
-
-fn gen_halve_rate(params: EnhancerParams, level: i32) -> f32 {
-  if level < params.min_halve_level {
-    return 0.0;
+  if params.value_spread > 0.0 {
+    let values = value_statistics(&simulations);
+    info!(
+      "Value at max: mean {:.3}, min {:.3}, p5 {:.3}, median {:.3}, max {:.3}",
+      values.mean, values.min, values.p5, values.median, values.max
+    );
   }
 
-  return gen_downgrade_rate(params, level) * params.halve_ratio;
-}
+  draw_plots(&args, &rates, &simulations);
 
-fn gen_reset_rate(params: EnhancerParams, level: i32) -> f32 {
-  if level < params.min_reset_level {
-    return 0.0;
+  if let Some(path) = &args.report_out {
+    debug!("Writing report");
+    Report::build(&params, &rates, &simulations, Some(seed))
+      .write_json(path)
+      .unwrap_or_else(| error | exit_with_error(format!("{}: {error}", path.display())));
   }
 
-  return gen_downgrade_rate(params, level) * params.reset_ratio;
+  info!("Data saved");
 }
 
-fn generate_rates(params: EnhancerParams) -> Vec::<EnhanceRate> {
-  let count = params.max_level + 1;
-  let mut rates = Vec::<EnhanceRate>::with_capacity(count as usize);
+#[cfg(feature = "plotting")]
+fn draw_plots(args: &Args, rates: &[EnhanceRate], simulations: &Vec::<EnhancerSimulation>) {
+  use std::path::Path;
 
-  for level in 0..count {
-    let value = gen_value(params, level);
-    let upgrade = gen_upgrade_rate(params, level);
-    let downgrade = gen_downgrade_rate(params, level);
-    let halve = gen_halve_rate(params, level);
-    let reset = gen_reset_rate(params, level);
+  let plot_error = | path: &Path, error: PlotError | exit_with_error(format!("{}: {error}", path.display()));
 
-    rates.push(EnhanceRate { level, value, upgrade, downgrade, halve, reset });
-  }
+  debug!("Drawing scatterplot");
+  draw_scatter_plot(simulations, &args.scatter_out, args.log_y).unwrap_or_else(| error | plot_error(&args.scatter_out, error));
 
-  return rates;
-}
+  debug!("Drawing box plot");
+  draw_box_plot(simulations, &args.box_out, args.log_y, &BoxPlotConfig::default()).unwrap_or_else(| error | plot_error(&args.box_out, error));
 
-fn roll(rate: EnhanceRate) -> EnhanceResult {
-  let roll = thread_rng().gen::<f32>();
+  debug!("Drawing median line plot");
+  let median_path = Path::new("median.svg");
+  draw_median_line_plot(simulations, median_path).unwrap_or_else(| error | plot_error(median_path, error));
 
-  let mut threshold = rate.reset;
-  if roll < threshold {
-    return EnhanceResult::Reset;
-  }
-
-  threshold += rate.halve;
-  if roll < threshold {
-    return EnhanceResult::Halve;
-  }
+  debug!("Drawing attempts CDF plot");
+  let cdf_path = Path::new("cdf.svg");
+  draw_cdf_plot(simulations, cdf_path).unwrap_or_else(| error | plot_error(cdf_path, error));
 
-  threshold += rate.downgrade;
-  if roll < threshold {
-    return EnhanceResult::Downgrade;
-  }
+  if rates.iter().any(| rate | rate.cost > 0.0) {
+    debug!("Drawing cost plots");
+    let cdf = cost_cdf(simulations);
+    let cost_range = cdf.last().map_or(0.0, | last | last.0 - cdf[0].0);
+    let bin_width = f64::max(f64::MIN_POSITIVE, cost_range / 30.0);
 
-  threshold += rate.upgrade;
-  if roll < threshold {
-    return EnhanceResult::Upgrade;
+    let histogram_path = Path::new("cost_histogram.svg");
+    draw_cost_histogram(simulations, bin_width, histogram_path).unwrap_or_else(| error | plot_error(histogram_path, error));
+    let cost_cdf_path = Path::new("cost_cdf.svg");
+    draw_cost_cdf(simulations, cost_cdf_path).unwrap_or_else(| error | plot_error(cost_cdf_path, error));
   }
 
-  return EnhanceResult::NoChange;
-}
-
-fn apply_result(level: i32, result: EnhanceResult) -> i32 {
-  match result {
-    EnhanceResult::NoChange => level,
-    EnhanceResult::Downgrade => level - 1,
-    EnhanceResult::Halve => level / 2,
-    EnhanceResult::Reset => 0,
-    EnhanceResult::Upgrade => level + 1
+  if let Some(path) = &args.dashboard_out {
+    debug!("Drawing dashboard");
+    draw_dashboard(simulations, path).unwrap_or_else(| error | plot_error(path, error));
   }
 }
 
-fn default_params() -> EnhancerParams {
-  return EnhancerParams {
-    max_level: 10,
-    value_increment: 0.125,
-    min_value: 1.0,
-    upgrade_rate_curve: 0.5,
-    max_upgrade_rate: 1.0,
-    min_upgrade_rate: 0.125,
-    downgrade_rate_curve: 0.5,
-    max_downgrade_rate: 0.5,
-    halve_ratio: 0.25,
-    reset_ratio: 0.0625,
-    min_downgrade_level: 1,
-    min_halve_level: 3,
-    min_reset_level: 5
-  };
-}
-
-fn main() {
-  let params = default_params();
-  let rates = generate_rates(params);
-  let mut simulations = EnhancerSimulation::create_many(&rates, 10000);
-  let mut iterations = 0;
-  let mut all_maxed = false;
-
-  let rates_table = EnhanceRate::format_table(&rates);
-  println!("Computed enhancement rates:");
-  print!("{rates_table}");
-
-  println!("Starting simulation of {} actors", simulations.len());
-  while !all_maxed {
-    iterations += 1;
-    all_maxed = EnhancerSimulation::enhance_many(&mut simulations);
-
-    if iterations % 2500 == 0 {
-      println!("Reached {iterations} iterations");
-    }
-  }
-  println!("Simulation complete at {iterations} iterations");
-
-  println!("Drawing scatterplot");
-  draw_scatter_plot(&simulations);
-
-  println!("Drawing box plot");
-  draw_box_plot(&simulations);
-
-  println!("Data saved")
+#[cfg(not(feature = "plotting"))]
+fn draw_plots(_args: &Args, _rates: &[EnhanceRate], _simulations: &Vec::<EnhancerSimulation>) {
+  info!("Plotting disabled, this build doesn't have the `plotting` feature");
 }
 
-fn scatter_x_axis(history_data: &mut Vec::<(f64, f64)>) {
-  let max_offset: f64 = 0.25;
-  let mut random = thread_rng();
+#[cfg(test)]
+mod tests {
+  use clap::CommandFactory;
+  use super::*;
 
-  for point in history_data {
-    let signed_roll = (random.gen::<f64>() * 2.0) -1.0;
-    let offset = max_offset * signed_roll;
-    point.0 = point.0 + offset;
+  #[test]
+  fn args_are_consistent() {
+    Args::command().debug_assert();
   }
-}
 
-fn draw_box_plot(simulations: &Vec::<EnhancerSimulation>) {
-  let history_data = EnhancerSimulation::boxplot_data(simulations);
-
-  let mut m_box_plots = Vec::<BoxPlot>::new();
-  let mut m_level_labels = Vec::<String>::new();
-  for i in 0..history_data.len() {
-    let level_set = &history_data[i];
-    let label = format!("{}", i);
-    m_box_plots.push(BoxPlot::from_vec(level_set.clone()).label(String::from(&label)).style(&BoxStyle::new().fill("#808080FF")));
-    m_level_labels.push(label);
+  #[test]
+  fn parses_representative_arguments() {
+    let args = Args::try_parse_from([
+      "enhancer", "--actors", "500", "--target-level", "7", "--seed", "42", "--scatter-out", "out/scatter.svg", "--log-y"
+    ]).unwrap();
+
+    assert_eq!(args.actors, 500);
+    assert_eq!(args.target_level, Some(7));
+    assert_eq!(args.seed, Some(42));
+    assert_eq!(args.scatter_out, PathBuf::from("out/scatter.svg"));
+    assert_eq!(args.box_out, PathBuf::from("box.svg"));
+    assert!(args.log_y);
+    assert!(args.config.is_none());
   }
 
-  let box_plots = m_box_plots;
-  let level_labels = m_level_labels;
-
-  let mut m_view = CategoricalView::new();
-    
-  for box_plot in box_plots {
-    m_view = m_view.add(box_plot);
+  #[test]
+  fn rejects_non_positive_actor_counts() {
+    assert!(Args::try_parse_from(["enhancer", "--actors", "0"]).is_err());
   }
-
-  let view = m_view
-    .x_ticks(&level_labels)
-    .y_range(0.0, 800.0)
-    .x_label("Enhancement Level")
-    .y_label("Total Attempts Taken To Reach (First Time)");
-
-  let page = Page::single(&view);
-  
-  page.save("box.svg").unwrap();
-}
-
-fn draw_scatter_plot(simulations: &Vec::<EnhancerSimulation>) {
-  // Scatter plots expect a list of pairs
-  let mut history = EnhancerSimulation::scatterplot_data(simulations);
-  scatter_x_axis(&mut history);
-  let history_data = history;
-
-  // We create our scatter plot from the data
-  let scatter_plot: Plot = Plot::new(history_data).point_style(
-    PointStyle::new()
-      .marker(PointMarker::Square)
-      .colour("#19CEA540")
-      .size(0.5)
-  );
-
-  // The 'view' describes what set of data is drawn
-  let v = ContinuousView::new()
-    .add(scatter_plot)
-    .x_range(0.0, 11.0)
-    .y_range(0.0, 800.0)
-    .x_label("Enhancement Level")
-    .y_label("Total Attempts Taken To Reach (First Time)");
-
-  // A page with a single view is then saved to an SVG file
-  Page::single(&v).save("scatter.svg").unwrap();
 }
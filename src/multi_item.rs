@@ -0,0 +1,123 @@
+// Several items enhanced by the same actor, e.g. a full set of gear that all has to reach max. The
+// items progress independently but draw their rolls from one shared generator
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use crate::params::EnhanceRules;
+use crate::rate::EnhanceRate;
+use crate::simulation::{ actor_seed, EnhancerSimulation, UniformRoll };
+
+#[derive(Clone, Debug)]
+pub struct MultiItemSimulation<'a> {
+  pub items: Vec::<EnhancerSimulation<'a>>,
+  rng: ChaCha8Rng
+}
+
+impl MultiItemSimulation<'_> {
+  pub fn create(rates: &Vec::<EnhanceRate>, item_count: i32) -> MultiItemSimulation<'_> {
+    return Self::create_seeded(rates, item_count, thread_rng().gen());
+  }
+
+  pub fn create_seeded(rates: &Vec::<EnhanceRate>, item_count: i32, seed: u64) -> MultiItemSimulation<'_> {
+    // Every attempt rolls from the shared `rng`, the items' own generators only draw the value
+    // rolled on reaching a level. They are seeded from `seed` too, so actors don't all get the
+    // same values
+    let items = (0..item_count)
+      .map(| i | EnhancerSimulation::create_seeded(rates, actor_seed(seed, i as u64)))
+      .collect();
+
+    return MultiItemSimulation { items, rng: ChaCha8Rng::seed_from_u64(seed) };
+  }
+
+  pub fn set_rules(&mut self, rules: EnhanceRules) {
+    EnhancerSimulation::set_rules_many(&mut self.items, rules);
+  }
+
+  // Attempts made across every item
+  pub fn total_attempts(&self) -> i32 {
+    return self.items.iter().map(| item | item.attempt_count).sum();
+  }
+
+  pub fn is_finished(&self) -> bool {
+    return self.items.iter().all(| item | item.is_finished());
+  }
+
+  // Makes one attempt on every item that isn't finished yet. Returns true once all of them are
+  pub fn enhance(&mut self) -> bool {
+    let mut source = UniformRoll(&mut self.rng);
+    for item in self.items.iter_mut() {
+      item.enhance_with(&mut source);
+    }
+
+    return self.is_finished();
+  }
+
+  // Returns the combined attempts once every item is finished
+  pub fn run_to_completion(&mut self) -> i32 {
+    while !self.enhance() {}
+
+    return self.total_attempts();
+  }
+}
+
+// Combined attempts to finish `item_count` items for each of `actors` actors, sorted ascending so it
+// can go straight into `percentile`. Actors are seeded as in `create_many_seeded`
+pub fn combined_attempts_distribution(rates: &Vec::<EnhanceRate>, item_count: i32, actors: i32, seed: u64, rules: EnhanceRules) -> Vec::<f64> {
+  let mut output = (0..actors)
+    .map(| i | {
+      let mut sim = MultiItemSimulation::create_seeded(rates, item_count, actor_seed(seed, i as u64));
+      sim.set_rules(rules);
+      sim.run_to_completion() as f64
+    })
+    .collect::<Vec::<f64>>();
+
+  output.sort_by(f64::total_cmp);
+  return output;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::params::{ default_params, generate_rates };
+
+  fn mean(samples: &Vec::<f64>) -> f64 {
+    return samples.iter().sum::<f64>() / samples.len() as f64;
+  }
+
+  #[test]
+  fn three_items_take_longer_than_one() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let rules = EnhanceRules::from_params(&default_params());
+
+    let single = combined_attempts_distribution(&rates, 1, 500, 1, rules);
+    let triple = combined_attempts_distribution(&rates, 3, 500, 1, rules);
+
+    assert!(mean(&triple) > 2.0 * mean(&single));
+    assert!(triple.windows(2).all(| pair | pair[0] <= pair[1]));
+  }
+
+  #[test]
+  fn item_values_differ_between_actors() {
+    let mut params = default_params();
+    params.value_spread = 0.5;
+    let rates = generate_rates(&params).unwrap();
+    let top = &rates[rates.len() - 1];
+
+    let values = (0..50)
+      .map(| seed | {
+        let mut sim = MultiItemSimulation::create_seeded(&rates, 2, seed);
+        sim.run_to_completion();
+        sim.items[0].current_value
+      })
+      .collect::<Vec::<f32>>();
+
+    assert!(values.iter().all(| &value | value >= top.value_min && value <= top.value_max));
+    let low = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let high = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    assert!(high - low > (top.value_max - top.value_min) * 0.5, "values only spread over [{low}, {high}]");
+
+    let first = MultiItemSimulation::create_seeded(&rates, 2, 1);
+    let second = MultiItemSimulation::create_seeded(&rates, 2, 2);
+    assert_ne!(first.items[0].rng, second.items[0].rng);
+    assert_ne!(first.items[0].rng, first.items[1].rng);
+  }
+}
@@ -0,0 +1,738 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{ Deserialize, Serialize };
+use crate::rate::EnhanceRate;
+
+// Fields missing from a config file fall back to `default_params`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnhancerParams {
+  pub max_level: i32,
+
+  // At level 0, value == min_value
+  // For each level after 1, value += value_increment
+  pub value_increment: f32,
+  pub min_value: f32,
+  // Reaching a level rolls its value uniformly within value * (1 +- value_spread) rather than
+  // always giving the exact value, 0 keeps values fixed
+  pub value_spread: f32,
+
+  // At level 0, upgrade rate == max_upgrade_rate
+  // For each level after 1, upgrade_rate *= upgrade_rate_curve (see `CurveShape` for the others)
+  pub upgrade_curve_shape: CurveShape,
+  pub upgrade_rate_curve: f32,
+  pub max_upgrade_rate: f32,
+  pub min_upgrade_rate: f32,
+
+  // Flat bonus added to the upgrade rate of every level below max, e.g. from a consumable.
+  // The bonus is taken out of the no change rate and never eats into the downgrade rates
+  pub upgrade_boost: f32,
+
+  // Fraction of the upgrade rate that instead becomes a great success, advancing two levels
+  pub great_success_ratio: f32,
+
+  // At level max, downgrade_rate == max_downgrade_rate
+  // For each level below max, downgrade_rate *= downgrade_rate_curve
+  pub downgrade_rate_curve: f32,
+  pub max_downgrade_rate: f32,
+  pub halve_ratio: f32,
+  // Which way a halve from an odd level rounds
+  pub halve_rounding: HalveRounding,
+  pub reset_ratio: f32,
+  // Destroying the item ends the actor's run for good
+  pub destroy_ratio: f32,
+  pub min_downgrade_level: i32,
+  pub min_halve_level: i32,
+  pub min_reset_level: i32,
+  // Safe levels a reset drops to instead of 0, the highest one at or below the level the reset
+  // happens at is used. Empty resets all the way to 0
+  pub reset_milestones: Vec::<i32>,
+  pub min_destroy_level: i32,
+
+  // Fraction of what was spent at the destroying level that is paid back on a destroy
+  pub destroy_refund_ratio: f32,
+
+  // Materials consumed by each attempt made at a level, indexed by level. Levels past the end of
+  // the list consume nothing
+  pub materials_per_attempt: Vec::<i32>,
+
+  // Currency cost of each attempt made at a level, indexed by level. Levels past the end of the
+  // list are free
+  pub cost_per_attempt: Vec::<f32>,
+
+  // Wall-clock seconds each attempt takes, e.g. an in-game cooldown. Levels listed in
+  // `seconds_per_attempt_by_level` use that duration instead
+  pub seconds_per_attempt: f32,
+  pub seconds_per_attempt_by_level: Vec::<f32>,
+
+  // Once an actor reaches this level it can never fall below it again, 0 disables protection
+  pub protected_level: i32,
+
+  // Charges that each cancel one downgrade, halve or reset that would have cost the actor levels,
+  // used up automatically. 0 disables them
+  pub protection_charges: i32,
+
+  // After a reset or halve, the actor's next this many attempts can't downgrade, halve or reset.
+  // 0 disables the grace period
+  pub reset_grace_attempts: i32,
+
+  // Every success in a row adds this much to the next attempt's upgrade rate, up to `momentum_cap`.
+  // Any other result drops the bonus back to 0
+  pub momentum_per_success: f32,
+  pub momentum_cap: f32,
+
+  // Every failed attempt in a row adds this much to the next attempt's upgrade rate, taken from the
+  // no change rate, until a success drops it back to 0. A gradual ramp for actors who are stuck
+  pub mercy_per_fail: f32,
+
+  // Actors still below max after this many attempts are abandoned instead of run forever
+  pub attempt_cap: Option::<i32>,
+
+  // Hand tuned rates for specific levels, keyed by level. Applied on top of the curves, any field
+  // an override leaves out keeps its curve value
+  pub overrides: HashMap::<i32, EnhanceRateOverride>
+}
+
+// Replacement values for one level of the generated rates table
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnhanceRateOverride {
+  pub value: Option::<f32>,
+  pub upgrade: Option::<f32>,
+  pub great_success: Option::<f32>,
+  pub downgrade: Option::<f32>,
+  pub halve: Option::<f32>,
+  pub reset: Option::<f32>,
+  pub destroy: Option::<f32>
+}
+
+impl EnhanceRateOverride {
+  pub fn apply(&self, rate: &mut EnhanceRate) {
+    rate.value = self.value.unwrap_or(rate.value);
+    rate.upgrade = self.upgrade.unwrap_or(rate.upgrade);
+    rate.great_success = self.great_success.unwrap_or(rate.great_success);
+    rate.downgrade = self.downgrade.unwrap_or(rate.downgrade);
+    rate.halve = self.halve.unwrap_or(rate.halve);
+    rate.reset = self.reset.unwrap_or(rate.reset);
+    rate.destroy = self.destroy.unwrap_or(rate.destroy);
+  }
+}
+
+// How the upgrade rate falls off from `max_upgrade_rate` as the level rises. Every shape is
+// clamped to `min_upgrade_rate`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveShape {
+  // The rate is multiplied by `upgrade_rate_curve` once per level, clamping at every step
+  #[default]
+  Geometric,
+  // The rate drops by `upgrade_rate_curve` per level
+  Linear,
+  // max_upgrade_rate * upgrade_rate_curve ^ level
+  Exponential
+}
+
+// Where a halve lands when the level is odd, e.g. from 5 Floor gives 2, Ceil gives 3 and Nearest 2
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HalveRounding {
+  #[default]
+  Floor,
+  Ceil,
+  // Rounds to the even neighbour, so halves round up and down equally often across odd levels
+  Nearest
+}
+
+impl HalveRounding {
+  pub fn halve(&self, level: i32) -> i32 {
+    return match self {
+      HalveRounding::Floor => level / 2,
+      HalveRounding::Ceil => (level + 1) / 2,
+      HalveRounding::Nearest => {
+        let half = level / 2;
+        if level % 2 == 1 && half % 2 == 1 { half + 1 } else { half }
+      }
+    };
+  }
+}
+
+// The parts of `EnhancerParams` that act on an actor's state rather than on the rates table
+// Rules missing from a checkpoint, e.g. one from before they were added, take their defaults
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnhanceRules {
+  pub protected_level: i32,
+  pub halve_rounding: HalveRounding,
+  pub destroy_refund_ratio: f32,
+  pub reset_grace_attempts: i32,
+  pub protection_charges: i32,
+  pub momentum_per_success: f32,
+  pub momentum_cap: f32,
+  pub mercy_per_fail: f32,
+  pub attempt_cap: Option::<i32>
+}
+
+impl EnhanceRules {
+  pub fn from_params(params: &EnhancerParams) -> EnhanceRules {
+    return EnhanceRules {
+      protected_level: params.protected_level,
+      halve_rounding: params.halve_rounding,
+      destroy_refund_ratio: params.destroy_refund_ratio,
+      reset_grace_attempts: params.reset_grace_attempts,
+      protection_charges: params.protection_charges,
+      momentum_per_success: params.momentum_per_success,
+      momentum_cap: params.momentum_cap,
+      mercy_per_fail: params.mercy_per_fail,
+      attempt_cap: params.attempt_cap
+    };
+  }
+}
+
+impl Default for EnhancerParams {
+  fn default() -> Self {
+    return default_params();
+  }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+  Io(io::Error),
+  Parse(toml::de::Error),
+  Json(serde_json::Error)
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ConfigError::Io(error) => write!(f, "could not read config: {error}"),
+      ConfigError::Parse(error) => write!(f, "invalid config: {error}"),
+      ConfigError::Json(error) => write!(f, "invalid params json: {error}")
+    }
+  }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Reads params from a TOML file, any field it leaves out keeps its default
+pub fn load_params(path: &Path) -> Result<EnhancerParams, ConfigError> {
+  let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+  return toml::from_str(&text).map_err(ConfigError::Parse);
+}
+
+// Same as `load_params` but for a JSON document, as piped in with `--params-stdin`
+pub fn params_from_json(text: &str) -> Result<EnhancerParams, ConfigError> {
+  return serde_json::from_str(text).map_err(ConfigError::Json);
+}
+
+// Reads a whole JSON document from `reader` and parses it with `params_from_json`
+pub fn read_params_json(mut reader: impl io::Read) -> Result<EnhancerParams, ConfigError> {
+  let mut text = String::new();
+  reader.read_to_string(&mut text).map_err(ConfigError::Io)?;
+  return params_from_json(&text);
+}
+
+// A parameter that can't produce a meaningful rates table
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidParamError {
+  pub field: &'static str,
+  // Set when the problem is with one level of the rates table rather than a whole field
+  pub level: Option::<i32>,
+  pub value: f32,
+  // What the value should have been, e.g. "within [0, 1]"
+  pub expected: &'static str
+}
+
+impl fmt::Display for InvalidParamError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return match self.level {
+      Some(level) => write!(f, "{} at level {level} must be {}, got {}", self.field, self.expected, self.value),
+      None => write!(f, "{} must be {}, got {}", self.field, self.expected, self.value)
+    };
+  }
+}
+
+impl std::error::Error for InvalidParamError {}
+
+fn check_unit_interval(field: &'static str, value: f32) -> Result<(), InvalidParamError> {
+  if !(0.0..=1.0).contains(&value) {
+    return Err(InvalidParamError { field, level: None, value, expected: "within [0, 1]" });
+  }
+
+  return Ok(());
+}
+
+fn check_non_negative(field: &'static str, value: f32) -> Result<(), InvalidParamError> {
+  // NaN fails the comparison too
+  if !(value >= 0.0 && value.is_finite()) {
+    return Err(InvalidParamError { field, level: None, value, expected: "finite and at least 0" });
+  }
+
+  return Ok(());
+}
+
+// Rejects params the rate generators would turn into garbage, such as curves above 1 that make
+// probabilities grow without bound, instead of finding out halfway through a long run
+pub fn validate_params(params: &EnhancerParams) -> Result<(), InvalidParamError> {
+  // Max level 0 is a single level that is already maxed, below that there are no levels at all
+  if params.max_level < 0 {
+    return Err(InvalidParamError { field: "max_level", level: None, value: params.max_level as f32, expected: "at least 0" });
+  }
+
+  // A negative floor would let losses push actors below level 0
+  if params.protected_level < 0 {
+    return Err(InvalidParamError { field: "protected_level", level: None, value: params.protected_level as f32, expected: "at least 0" });
+  }
+
+  // No actor could ever reach it, so the protection would silently never kick in
+  if params.protected_level > params.max_level {
+    return Err(InvalidParamError { field: "protected_level", level: None, value: params.protected_level as f32, expected: "at most max_level" });
+  }
+
+  if let Some(attempt_cap) = params.attempt_cap {
+    if attempt_cap < 0 {
+      return Err(InvalidParamError { field: "attempt_cap", level: None, value: attempt_cap as f32, expected: "at least 0" });
+    }
+  }
+
+  check_non_negative("value_increment", params.value_increment)?;
+  check_non_negative("min_value", params.min_value)?;
+  check_unit_interval("value_spread", params.value_spread)?;
+  check_unit_interval("upgrade_rate_curve", params.upgrade_rate_curve)?;
+  check_unit_interval("max_upgrade_rate", params.max_upgrade_rate)?;
+  check_unit_interval("min_upgrade_rate", params.min_upgrade_rate)?;
+  check_unit_interval("upgrade_boost", params.upgrade_boost)?;
+  check_unit_interval("great_success_ratio", params.great_success_ratio)?;
+  check_unit_interval("downgrade_rate_curve", params.downgrade_rate_curve)?;
+  check_unit_interval("max_downgrade_rate", params.max_downgrade_rate)?;
+  check_unit_interval("halve_ratio", params.halve_ratio)?;
+  check_unit_interval("reset_ratio", params.reset_ratio)?;
+  check_unit_interval("destroy_ratio", params.destroy_ratio)?;
+  check_unit_interval("destroy_refund_ratio", params.destroy_refund_ratio)?;
+  check_unit_interval("momentum_per_success", params.momentum_per_success)?;
+  check_unit_interval("momentum_cap", params.momentum_cap)?;
+  check_unit_interval("mercy_per_fail", params.mercy_per_fail)?;
+  check_non_negative("seconds_per_attempt", params.seconds_per_attempt)?;
+
+  for &cost in &params.cost_per_attempt {
+    check_non_negative("cost_per_attempt", cost)?;
+  }
+
+  for &materials in &params.materials_per_attempt {
+    if materials < 0 {
+      return Err(InvalidParamError { field: "materials_per_attempt", level: None, value: materials as f32, expected: "at least 0" });
+    }
+  }
+
+  for &seconds in &params.seconds_per_attempt_by_level {
+    check_non_negative("seconds_per_attempt_by_level", seconds)?;
+  }
+
+  for &milestone in &params.reset_milestones {
+    if milestone < 0 {
+      return Err(InvalidParamError { field: "reset_milestones", level: None, value: milestone as f32, expected: "at least 0" });
+    }
+  }
+
+  return Ok(());
+}
+
+// Every outcome of a level has to be a probability and together they can't exceed certainty
+fn check_rate(field: &'static str, rate: &EnhanceRate) -> Result<(), InvalidParamError> {
+  let level = Some(rate.level);
+  for probability in [rate.upgrade, rate.great_success, rate.downgrade, rate.halve, rate.reset, rate.destroy] {
+    if !(0.0..=1.0).contains(&probability) {
+      return Err(InvalidParamError { field, level, value: probability, expected: "within [0, 1]" });
+    }
+  }
+
+  if !(rate.value.is_finite() && rate.value >= 0.0) {
+    return Err(InvalidParamError { field, level, value: rate.value, expected: "a finite value of at least 0" });
+  }
+
+  // Leeway for the rounding of summing several f32 probabilities
+  let total = 1.0 - rate.no_change_rate();
+  if total > 1.0 + 1e-6 {
+    return Err(InvalidParamError { field, level, value: total, expected: "a total probability of at most 1" });
+  }
+
+  return Ok(());
+}
+
+// Every generated probability has to be usable as is, validation should have caught anything else
+fn debug_assert_probability(name: &str, level: i32, rate: f32) {
+  debug_assert!(rate.is_finite() && (0.0..=1.0).contains(&rate), "{name} rate at level {level} is {rate}");
+}
+
+fn gen_value(params: &EnhancerParams, level: i32) -> f32 {
+  let mut value = params.min_value;
+  for _i in 0..level {
+    value += params.value_increment;
+  }
+
+  return value;
+}
+
+fn gen_upgrade_rate(params: &EnhancerParams, level: i32) -> f32 {
+  if level >= params.max_level {
+    return 0.0;
+  }
+
+  let mut upgrade_rate = match params.upgrade_curve_shape {
+    CurveShape::Geometric => {
+      let mut rate = params.max_upgrade_rate;
+      for _i in 0..level {
+        rate = f32::max(params.min_upgrade_rate, rate * params.upgrade_rate_curve);
+      }
+      rate
+    },
+    CurveShape::Linear => params.max_upgrade_rate - params.upgrade_rate_curve * level as f32,
+    CurveShape::Exponential => params.max_upgrade_rate * params.upgrade_rate_curve.powi(level)
+  };
+  upgrade_rate = f32::max(params.min_upgrade_rate, upgrade_rate);
+
+  if params.upgrade_boost > 0.0 {
+    let loss_rate = gen_downgrade_rate(params, level) + gen_halve_rate(params, level) + gen_reset_rate(params, level) + gen_destroy_rate(params, level);
+    let boosted_rate = f32::min(upgrade_rate + params.upgrade_boost, 1.0 - loss_rate);
+    upgrade_rate = f32::max(upgrade_rate, boosted_rate);
+  }
+
+  return upgrade_rate;
+}
+
+fn gen_downgrade_rate(params: &EnhancerParams, level: i32) -> f32 {
+  if level >= params.max_level {
+    return 0.0;
+  }
+
+  if level < params.min_downgrade_level {
+    return 0.0;
+  }
+
+  let mut downgrade_rate = params.max_downgrade_rate;
+  let count = params.max_level - level - 1;
+  for _i in 0..count {
+    downgrade_rate *= params.downgrade_rate_curve;
+  }
+
+  return downgrade_rate;
+}
+
+fn gen_halve_rate(params: &EnhancerParams, level: i32) -> f32 {
+  if level < params.min_halve_level {
+    return 0.0;
+  }
+
+  return gen_downgrade_rate(params, level) * params.halve_ratio;
+}
+
+fn gen_reset_rate(params: &EnhancerParams, level: i32) -> f32 {
+  if level < params.min_reset_level {
+    return 0.0;
+  }
+
+  return gen_downgrade_rate(params, level) * params.reset_ratio;
+}
+
+fn gen_reset_level(params: &EnhancerParams, level: i32) -> i32 {
+  return params.reset_milestones.iter()
+    .copied()
+    .filter(| &milestone | milestone <= level)
+    .max()
+    .unwrap_or(0);
+}
+
+fn gen_destroy_rate(params: &EnhancerParams, level: i32) -> f32 {
+  if level < params.min_destroy_level {
+    return 0.0;
+  }
+
+  return gen_downgrade_rate(params, level) * params.destroy_ratio;
+}
+
+pub fn generate_rates(params: &EnhancerParams) -> Result<Vec::<EnhanceRate>, InvalidParamError> {
+  validate_params(params)?;
+
+  let count = params.max_level + 1;
+  let mut rates = Vec::<EnhanceRate>::with_capacity(count as usize);
+
+  for level in 0..count {
+    let value = gen_value(params, level);
+    let advance = gen_upgrade_rate(params, level);
+    let great_success = advance * params.great_success_ratio;
+    let upgrade = advance - great_success;
+    let downgrade = gen_downgrade_rate(params, level);
+    let halve = gen_halve_rate(params, level);
+    let reset = gen_reset_rate(params, level);
+    let destroy = gen_destroy_rate(params, level);
+
+    debug_assert_probability("upgrade", level, upgrade);
+    debug_assert_probability("great success", level, great_success);
+    debug_assert_probability("downgrade", level, downgrade);
+    debug_assert_probability("halve", level, halve);
+    debug_assert_probability("reset", level, reset);
+    debug_assert_probability("destroy", level, destroy);
+
+    let materials = params.materials_per_attempt.get(level as usize).copied().unwrap_or(0);
+    let cost = params.cost_per_attempt.get(level as usize).copied().unwrap_or(0.0);
+    let seconds = params.seconds_per_attempt_by_level.get(level as usize).copied().unwrap_or(params.seconds_per_attempt);
+    let reset_level = gen_reset_level(params, level);
+
+    let mut rate = EnhanceRate {
+      level,
+      value,
+      value_min: value,
+      value_max: value,
+      upgrade,
+      great_success,
+      downgrade,
+      halve,
+      reset,
+      destroy,
+      reset_level,
+      materials,
+      cost,
+      seconds
+    };
+
+    let field = match params.overrides.get(&level) {
+      Some(rate_override) => {
+        rate_override.apply(&mut rate);
+        "overrides"
+      },
+      None => "rates"
+    };
+    check_rate(field, &rate)?;
+
+    // Applied after the overrides so an overridden value gets the same spread
+    rate.value_min = rate.value * (1.0 - params.value_spread);
+    rate.value_max = rate.value * (1.0 + params.value_spread);
+
+    rates.push(rate);
+  }
+
+  return Ok(rates);
+}
+
+pub fn default_params() -> EnhancerParams {
+  return EnhancerParams {
+    max_level: 10,
+    value_increment: 0.125,
+    min_value: 1.0,
+    value_spread: 0.0,
+    upgrade_curve_shape: CurveShape::Geometric,
+    upgrade_rate_curve: 0.5,
+    max_upgrade_rate: 1.0,
+    min_upgrade_rate: 0.125,
+    upgrade_boost: 0.0,
+    great_success_ratio: 0.0,
+    downgrade_rate_curve: 0.5,
+    max_downgrade_rate: 0.5,
+    halve_ratio: 0.25,
+    halve_rounding: HalveRounding::Floor,
+    reset_ratio: 0.0625,
+    destroy_ratio: 0.0,
+    min_downgrade_level: 1,
+    min_halve_level: 3,
+    min_reset_level: 5,
+    reset_milestones: Vec::new(),
+    min_destroy_level: 7,
+    destroy_refund_ratio: 0.0,
+    materials_per_attempt: Vec::new(),
+    cost_per_attempt: Vec::new(),
+    seconds_per_attempt: 0.0,
+    seconds_per_attempt_by_level: Vec::new(),
+    protected_level: 0,
+    reset_grace_attempts: 0,
+    protection_charges: 0,
+    momentum_per_success: 0.0,
+    momentum_cap: 0.0,
+    mercy_per_fail: 0.0,
+    attempt_cap: None,
+    overrides: HashMap::new()
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use rand::prelude::*;
+  use rand_chacha::ChaCha8Rng;
+  use super::*;
+  use crate::simulation::EnhancerSimulation;
+  use crate::testing::seeded_mean_attempts;
+
+  #[test]
+  fn upgrade_boost_makes_runs_faster() {
+    let base = default_params();
+    let boosted = EnhancerParams { upgrade_boost: 0.2, ..default_params() };
+
+    let base_mean = seeded_mean_attempts(&base, 1000, 1);
+    let boosted_mean = seeded_mean_attempts(&boosted, 1000, 1);
+    assert!(boosted_mean < base_mean, "boosted mean {boosted_mean} is not below base mean {base_mean}");
+  }
+
+  fn upgrade_rates(shape: CurveShape, curve: f32) -> Vec::<f32> {
+    let params = EnhancerParams { upgrade_curve_shape: shape, upgrade_rate_curve: curve, min_upgrade_rate: 0.05, ..default_params() };
+    return generate_rates(&params).unwrap().iter().map(| rate | rate.upgrade).collect();
+  }
+
+  fn assert_close(actual: f32, expected: f32) {
+    assert!((actual - expected).abs() < 1e-6, "expected {expected}, got {actual}");
+  }
+
+  #[test]
+  fn curve_shapes_pin_start_middle_and_end_rates() {
+    let geometric = upgrade_rates(CurveShape::Geometric, 0.8);
+    assert_close(geometric[0], 1.0);
+    assert_close(geometric[5], 0.8f32.powi(5));
+    assert_close(geometric[9], 0.8f32.powi(9));
+
+    let linear = upgrade_rates(CurveShape::Linear, 0.1);
+    assert_close(linear[0], 1.0);
+    assert_close(linear[5], 0.5);
+    assert_close(linear[9], 0.1);
+
+    let exponential = upgrade_rates(CurveShape::Exponential, 0.7);
+    assert_close(exponential[0], 1.0);
+    assert_close(exponential[5], 0.7f32.powi(5));
+    // 0.7^9 is below the minimum
+    assert_close(exponential[9], 0.05);
+
+    // Max level can't be upgraded from, whatever the shape
+    assert_eq!(geometric[10], 0.0);
+    assert_eq!(linear[10], 0.0);
+    assert_eq!(exponential[10], 0.0);
+  }
+
+  #[test]
+  fn linear_curve_clamps_at_the_minimum() {
+    let linear = upgrade_rates(CurveShape::Linear, 0.2);
+    assert_close(linear[4], 0.2);
+    assert_close(linear[5], 0.05);
+    assert_close(linear[9], 0.05);
+  }
+
+  #[test]
+  fn json_params_override_only_the_given_fields() {
+    let text = r#"{ "max_level": 12, "upgrade_rate_curve": 0.75, "halve_rounding": "ceil", "attempt_cap": 500 }"#;
+    let params = read_params_json(text.as_bytes()).unwrap();
+
+    assert_eq!(params.max_level, 12);
+    assert_eq!(params.upgrade_rate_curve, 0.75);
+    assert_eq!(params.halve_rounding, HalveRounding::Ceil);
+    assert_eq!(params.attempt_cap, Some(500));
+    assert_eq!(params.reset_ratio, default_params().reset_ratio);
+  }
+
+  #[test]
+  fn malformed_json_params_are_rejected() {
+    assert!(matches!(params_from_json("{ \"max_level\": \"ten\" }"), Err(ConfigError::Json(_))));
+  }
+
+  #[test]
+  fn curve_above_one_is_rejected() {
+    let params = EnhancerParams { upgrade_rate_curve: 2.0, ..default_params() };
+    let error = generate_rates(&params).unwrap_err();
+
+    assert_eq!(error.field, "upgrade_rate_curve");
+    assert_eq!(error.value, 2.0);
+    assert_eq!(error.to_string(), "upgrade_rate_curve must be within [0, 1], got 2");
+  }
+
+  #[test]
+  fn non_finite_params_are_rejected() {
+    assert!(generate_rates(&EnhancerParams { halve_ratio: f32::NAN, ..default_params() }).is_err());
+    assert!(generate_rates(&EnhancerParams { value_increment: f32::INFINITY, ..default_params() }).is_err());
+  }
+
+  #[test]
+  fn halve_of_an_odd_level_under_every_rounding() {
+    assert_eq!(HalveRounding::Floor.halve(7), 3);
+    assert_eq!(HalveRounding::Ceil.halve(7), 4);
+    assert_eq!(HalveRounding::Nearest.halve(7), 4);
+
+    // 2.5 and 4.5 go down to the even level, unlike Ceil
+    assert_eq!(HalveRounding::Ceil.halve(5), 3);
+    assert_eq!(HalveRounding::Nearest.halve(5), 2);
+    assert_eq!(HalveRounding::Nearest.halve(9), 4);
+    assert_eq!(HalveRounding::Nearest.halve(3), 2);
+    assert_eq!(HalveRounding::Nearest.halve(1), 0);
+
+    for rounding in [HalveRounding::Floor, HalveRounding::Ceil, HalveRounding::Nearest] {
+      assert_eq!(rounding.halve(8), 4);
+    }
+  }
+
+  #[test]
+  fn override_only_changes_its_own_level() {
+    let mut params = default_params();
+    let curve = generate_rates(&params).unwrap();
+    params.overrides.insert(8, EnhanceRateOverride { upgrade: Some(0.05), ..EnhanceRateOverride::default() });
+    let overridden = generate_rates(&params).unwrap();
+
+    assert_eq!(overridden[8].upgrade, 0.05);
+    assert_eq!(overridden[8].downgrade, curve[8].downgrade);
+    for level in (0..curve.len()).filter(| &level | level != 8) {
+      assert_eq!(overridden[level].upgrade, curve[level].upgrade);
+      assert_eq!(overridden[level].downgrade, curve[level].downgrade);
+    }
+  }
+
+  #[test]
+  fn random_params_never_panic_or_leave_the_level_range() {
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+
+    for _case in 0..500 {
+      let mut params = default_params();
+      params.max_level = rng.gen_range(-3..=12);
+      params.upgrade_curve_shape = *[CurveShape::Geometric, CurveShape::Linear, CurveShape::Exponential].choose(&mut rng).unwrap();
+      params.upgrade_rate_curve = rng.gen_range(0.0..=1.2);
+      params.min_upgrade_rate = rng.gen_range(0.0..=0.5);
+      params.upgrade_boost = rng.gen_range(0.0..=0.3);
+      params.great_success_ratio = rng.gen_range(0.0..=1.0);
+      params.max_downgrade_rate = rng.gen_range(0.0..=0.9);
+      params.halve_ratio = rng.gen_range(0.0..=0.5);
+      params.reset_ratio = rng.gen_range(0.0..=0.5);
+      params.destroy_ratio = rng.gen_range(0.0..=0.5);
+      params.min_downgrade_level = rng.gen_range(-3..=12);
+      params.min_halve_level = rng.gen_range(-3..=12);
+      params.min_reset_level = rng.gen_range(-3..=12);
+      params.min_destroy_level = rng.gen_range(-3..=12);
+      params.reset_milestones = (0..rng.gen_range(0..3)).map(| _ | rng.gen_range(-3..=12)).collect();
+      params.protected_level = rng.gen_range(-3..=12);
+      params.materials_per_attempt = (0..rng.gen_range(0..4)).map(| _ | rng.gen_range(-2..=5)).collect();
+      params.protection_charges = rng.gen_range(0..=5);
+      params.reset_grace_attempts = rng.gen_range(0..=5);
+      params.attempt_cap = if rng.gen_bool(0.5) { Some(rng.gen_range(-50..=500)) } else { None };
+
+      // Nonsense combinations are for `generate_rates` to reject, not to simulate
+      let Ok(rates) = generate_rates(&params) else {
+        continue;
+      };
+
+      let mut simulations = EnhancerSimulation::create_many_seeded(&rates, 20, rng.gen());
+      EnhancerSimulation::set_rules_many(&mut simulations, EnhanceRules::from_params(&params));
+      let _ = EnhancerSimulation::run_capped(&mut simulations, 1_000, | _progress | {});
+
+      assert!(params.protected_level <= params.max_level);
+      assert!(params.attempt_cap.is_none_or(| cap | cap >= 0));
+      for simulation in simulations.iter() {
+        assert!((0..=params.max_level).contains(&simulation.level), "level {} out of range for {:?}", simulation.level, params);
+        assert!(simulation.materials_used >= 0);
+      }
+    }
+  }
+
+  #[test]
+  fn unreachable_protection_and_negative_counts_are_rejected() {
+    let rejected_field = | params: EnhancerParams | generate_rates(&params).unwrap_err().field;
+
+    assert_eq!(rejected_field(EnhancerParams { protected_level: 11, ..default_params() }), "protected_level");
+    assert_eq!(rejected_field(EnhancerParams { attempt_cap: Some(-1), ..default_params() }), "attempt_cap");
+    assert_eq!(rejected_field(EnhancerParams { materials_per_attempt: vec![1, -1], ..default_params() }), "materials_per_attempt");
+
+    assert!(generate_rates(&EnhancerParams { protected_level: 10, attempt_cap: Some(0), ..default_params() }).is_ok());
+  }
+}
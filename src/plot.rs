@@ -0,0 +1,927 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use log::warn;
+use plotlib::page::Page;
+use plotlib::repr::{ BoxPlot, Histogram, HistogramBins, Plot };
+use plotlib::view::{ CategoricalView, ContinuousView, View };
+use plotlib::style::{ BoxStyle, LineStyle, PointMarker, PointStyle };
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use svg::Document;
+use svg::Node;
+use crate::simulation::EnhancerSimulation;
+use crate::stats::{ attempts_cdf, completed_actors, cost_cdf, level_mean_intervals, level_statistics, percentile, LevelStats };
+use crate::sweep::ParamField;
+
+#[derive(Debug)]
+pub enum PlotError {
+  Io(io::Error),
+  // plotlib's own errors, kept as text since its error type isn't part of our API
+  Render(String),
+  // A cost plot was asked for but every attempt in the rates table is free
+  NoCostModel,
+  // `Whiskers::Percentiles` bounds that aren't 0 <= low <= 0.25 and 0.75 <= high <= 1, i.e. that
+  // would end inside the box
+  InvalidWhiskers(f64, f64)
+}
+
+impl PlotError {
+  fn render(error: impl fmt::Display) -> PlotError {
+    return PlotError::Render(error.to_string());
+  }
+
+  // Write failures that might go away on their own, such as a networked filesystem hiccup. Anything
+  // else, a missing directory or a denied permission say, will fail the same way every time
+  fn is_transient(&self) -> bool {
+    return match self {
+      PlotError::Io(error) => matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted
+          | io::ErrorKind::TimedOut
+          | io::ErrorKind::WouldBlock
+          | io::ErrorKind::ConnectionReset
+          | io::ErrorKind::ConnectionAborted
+          | io::ErrorKind::BrokenPipe
+      ),
+      PlotError::Render(_) | PlotError::NoCostModel | PlotError::InvalidWhiskers(_, _) => false
+    };
+  }
+}
+
+impl fmt::Display for PlotError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PlotError::Io(error) => write!(f, "could not write plot: {error}"),
+      PlotError::Render(error) => write!(f, "could not render plot: {error}"),
+      PlotError::NoCostModel => write!(f, "no attempt costs are configured, there is no cost to plot"),
+      PlotError::InvalidWhiskers(low, high) => {
+        write!(f, "whisker percentiles ({low}, {high}) must satisfy 0 <= low <= 0.25 and 0.75 <= high <= 1")
+      }
+    }
+  }
+}
+
+impl std::error::Error for PlotError {}
+
+impl From<io::Error> for PlotError {
+  fn from(error: io::Error) -> Self {
+    return PlotError::Io(error);
+  }
+}
+
+// Attempts the plots written at the end of a long run make before giving up
+pub const PLOT_SAVE_ATTEMPTS: u32 = 3;
+
+// Wait before the first retry, doubled for every retry after it
+const SAVE_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+// Runs `save` up to `attempts` times, backing off between tries, until it succeeds or fails with an
+// error that isn't transient
+fn retry_save(path: &Path, attempts: u32, mut save: impl FnMut() -> Result<(), PlotError>) -> Result<(), PlotError> {
+  let mut delay = SAVE_RETRY_DELAY;
+  let mut attempt = 1;
+  loop {
+    match save() {
+      Err(error) if error.is_transient() && attempt < attempts => {
+        warn!("Saving {} failed ({error}), retrying in {}ms", path.display(), delay.as_millis());
+        thread::sleep(delay);
+        delay *= 2;
+        attempt += 1;
+      }
+      result => return result
+    }
+  }
+}
+
+// Saves `page` as an SVG, retrying transient write failures. The page is only rendered once
+pub fn save_with_retry(page: &Page, path: &Path, attempts: u32) -> Result<(), PlotError> {
+  let document = page.to_svg().map_err(PlotError::render)?;
+  return save_document_with_retry(&document, path, attempts);
+}
+
+fn save_document_with_retry(document: &Document, path: &Path, attempts: u32) -> Result<(), PlotError> {
+  return retry_save(path, attempts, || Ok(svg::save(path, document)?));
+}
+
+// Max level of the rates the batch was run against, 0 for an empty batch
+fn batch_max_level(simulations: &Vec::<EnhancerSimulation>) -> usize {
+  return simulations.first().map_or(0, | sim | sim.rates.len().saturating_sub(1));
+}
+
+// Axis label noting the top levels no actor reached, those have no samples and are left out
+fn level_axis_label(simulations: &Vec::<EnhancerSimulation>) -> String {
+  let reached = simulations.iter().map(| sim | sim.history.len()).max().unwrap_or(0);
+  let max_level = batch_max_level(simulations);
+
+  return match reached {
+    0 => String::from("Enhancement Level (no data)"),
+    _ if reached > max_level => String::from("Enhancement Level"),
+    _ if reached == max_level => format!("Enhancement Level ({max_level} never reached)"),
+    _ => format!("Enhancement Level ({reached}-{max_level} never reached)")
+  };
+}
+
+// Jitter is fixed rather than random so the same batch always gives the same plot
+const JITTER_SEED: u64 = 0;
+
+// Jitter half-widths, the busiest level gets the widest and sparse levels shrink towards the
+// narrowest. Even the widest stays clear of the neighbouring levels
+const MIN_JITTER: f64 = 0.05;
+const MAX_JITTER: f64 = 0.4;
+
+// Spreads the points of each level sideways so stacked samples stay visible. The width scales with
+// the square root of how many points share the level, so it tracks the spread of a dense column
+// without a handful of points at the top levels looking lost in a wide band
+fn scatter_x_axis(history_data: &mut Vec::<(f64, f64)>) {
+  let mut random = ChaCha8Rng::seed_from_u64(JITTER_SEED);
+
+  let mut level_counts = Vec::<usize>::new();
+  for point in history_data.iter() {
+    let level = point.0 as usize;
+    if level >= level_counts.len() {
+      level_counts.resize(level + 1, 0);
+    }
+    level_counts[level] += 1;
+  }
+  let max_count = level_counts.iter().copied().max().unwrap_or(0).max(1);
+
+  for point in history_data {
+    let density = (level_counts[point.0 as usize] as f64 / max_count as f64).sqrt();
+    let max_offset = MIN_JITTER + (MAX_JITTER - MIN_JITTER) * density;
+
+    let signed_roll = (random.gen::<f64>() * 2.0) -1.0;
+    let offset = max_offset * signed_roll;
+    point.0 += offset;
+  }
+}
+
+const ATTEMPTS_LABEL: &str = "Total Attempts Taken To Reach (First Time)";
+const LOG_ATTEMPTS_LABEL: &str = "log10(1 + Total Attempts Taken To Reach (First Time))";
+const LINEAR_ATTEMPTS_MAX: f64 = 800.0;
+
+// Level 0 is reached after 0 attempts, so the log scale is offset by one to keep it finite
+fn log_attempts(attempts: f64) -> f64 {
+  return (attempts + 1.0).log10();
+}
+
+// Returns the y range maximum and label for an attempts axis holding values up to `max_attempts`
+fn attempts_axis(log_y: bool, max_attempts: f64) -> (f64, &'static str) {
+  if log_y {
+    return (f64::max(1.0, log_attempts(max_attempts) * 1.05), LOG_ATTEMPTS_LABEL);
+  }
+
+  return (LINEAR_ATTEMPTS_MAX, ATTEMPTS_LABEL);
+}
+
+// Where the box plot whiskers end
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Whiskers {
+  // plotlib's own whiskers, out to the smallest and largest sample
+  Range,
+  // Out to these two percentiles, e.g. (0.05, 0.95). Samples past them are outliers. The whiskers
+  // can't end inside the box, so low is at most 0.25 and high at least 0.75
+  Percentiles(f64, f64)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoxPlotConfig {
+  pub fill: String,
+  pub whiskers: Whiskers,
+  // Draws the samples past the whiskers as points, there are none with `Whiskers::Range`
+  pub show_outliers: bool
+}
+
+impl Default for BoxPlotConfig {
+  fn default() -> Self {
+    return BoxPlotConfig {
+      fill: String::from("#808080FF"),
+      whiskers: Whiskers::Range,
+      show_outliers: false
+    };
+  }
+}
+
+impl BoxPlotConfig {
+  pub fn box_style(&self) -> BoxStyle {
+    return BoxStyle::new().fill(self.fill.as_str());
+  }
+
+  // Lowest and highest value the whiskers reach for already sorted samples. Never inside the samples
+  // the box is drawn from, so clamping to them can't move the quartiles
+  fn whisker_bounds(&self, sorted: &[f64]) -> (f64, f64) {
+    return match self.whiskers {
+      Whiskers::Range => (sorted[0], sorted[sorted.len() - 1]),
+      Whiskers::Percentiles(low, high) => {
+        let (box_low, box_high) = box_samples(sorted);
+        (f64::min(percentile(sorted, low), box_low), f64::max(percentile(sorted, high), box_high))
+      }
+    };
+  }
+}
+
+// Lowest and highest of the already sorted samples that plotlib's quartiles are taken from. It uses
+// the medians of the lower and upper halves, leaving out the middle sample of an odd count, and a
+// small batch can put those well past the 25th and 75th percentiles
+fn box_samples(sorted: &[f64]) -> (f64, f64) {
+  let n = sorted.len();
+  let half = n / 2;
+  if half == 0 {
+    return (sorted[0], sorted[0]);
+  }
+
+  return (sorted[(half - 1) / 2], sorted[n - half + half / 2]);
+}
+
+// Moves the samples past the whiskers onto them, since plotlib draws its whiskers out to the
+// smallest and largest sample. Returns the moved samples' original values, `sorted` stays sorted
+fn clamp_to_whiskers(sorted: &mut [f64], config: &BoxPlotConfig) -> Vec::<f64> {
+  let (low, high) = config.whisker_bounds(sorted);
+  let mut outliers = Vec::<f64>::new();
+  for y in sorted.iter_mut() {
+    if *y < low || *y > high {
+      outliers.push(*y);
+      *y = y.clamp(low, high);
+    }
+  }
+
+  return outliers;
+}
+
+pub fn draw_box_plot(simulations: &Vec::<EnhancerSimulation>, path: &Path, log_y: bool, config: &BoxPlotConfig) -> Result<(), PlotError> {
+  if let Whiskers::Percentiles(low, high) = config.whiskers {
+    // Also catches NaN, which fails every comparison
+    if !((0.0..=0.25).contains(&low) && (0.75..=1.0).contains(&high)) {
+      return Err(PlotError::InvalidWhiskers(low, high));
+    }
+  }
+
+  let (view, outliers) = box_plot_view(simulations, log_y, config);
+  let mut document = Page::single(&view).to_svg().map_err(PlotError::render)?;
+
+  if config.show_outliers && !outliers.is_empty() {
+    let (y_max, _) = box_plot_axis(simulations, log_y);
+    document.append(box_plot_outlier_points(&outliers, view_level_count(simulations), y_max));
+  }
+
+  return save_document_with_retry(&document, path, PLOT_SAVE_ATTEMPTS);
+}
+
+fn view_level_count(simulations: &Vec::<EnhancerSimulation>) -> usize {
+  return simulations.iter().map(| sim | sim.history.len()).max().unwrap_or(0);
+}
+
+fn box_plot_axis(simulations: &Vec::<EnhancerSimulation>, log_y: bool) -> (f64, &'static str) {
+  let max_attempts = simulations.iter().flat_map(| sim | sim.history.iter()).fold(0.0, | max, &y | f64::max(max, y as f64));
+  return attempts_axis(log_y, max_attempts);
+}
+
+// The samples are clamped to the configured whiskers first, see `clamp_to_whiskers`. Also returns
+// the clamped samples as (level, value) for drawing as outliers
+fn box_plot_view(simulations: &Vec::<EnhancerSimulation>, log_y: bool, config: &BoxPlotConfig) -> (CategoricalView, Vec::<(usize, f64)>) {
+  let mut history_data = EnhancerSimulation::boxplot_data(simulations);
+
+  if log_y {
+    for y in history_data.iter_mut().flatten() {
+      *y = log_attempts(*y);
+    }
+  }
+
+  let (y_max, y_label) = box_plot_axis(simulations, log_y);
+  let style = config.box_style();
+
+  // Each level's samples are moved into its box plot rather than cloned, so the attempt data is
+  // only held once
+  let mut m_box_plots = Vec::<BoxPlot>::new();
+  let mut m_level_labels = Vec::<String>::new();
+  let mut m_outliers = Vec::<(usize, f64)>::new();
+  for (i, mut level_set) in history_data.into_iter().enumerate() {
+    level_set.sort_by(f64::total_cmp);
+    m_outliers.extend(clamp_to_whiskers(&mut level_set, config).into_iter().map(| y | (i, y)));
+
+    let label = format!("{}", i);
+    m_box_plots.push(BoxPlot::from_vec(level_set).label(String::from(&label)).style(&style));
+    m_level_labels.push(label);
+  }
+
+  let box_plots = m_box_plots;
+  let level_labels = m_level_labels;
+
+  let mut m_view = CategoricalView::new();
+
+  for box_plot in box_plots {
+    m_view = m_view.add(box_plot);
+  }
+
+  let view = m_view
+    .x_ticks(&level_labels)
+    .y_range(0.0, y_max)
+    .x_label(level_axis_label(simulations))
+    .y_label(y_label);
+
+  return (view, m_outliers);
+}
+
+// Outliers as small circles over the box plot, placed the way plotlib places a categorical view on
+// a default `Page`: each level gets an equal slice of the face with its box in the middle
+fn box_plot_outlier_points(outliers: &Vec::<(usize, f64)>, level_count: usize, y_max: f64) -> svg::node::element::Group {
+  let (width, height) = (600.0, 400.0);
+  let (x_margin, y_margin) = (120.0, 60.0);
+  let face_width = width - x_margin;
+  let face_height = height - y_margin;
+  let space_per_level = face_width / level_count as f64;
+
+  let mut group = svg::node::element::Group::new()
+    .set("transform", format!("translate({}, {})", 0.6 * x_margin, height - 0.6 * y_margin));
+
+  // Values past the top of the axis would be drawn outside the plot
+  for &(level, y) in outliers.iter().filter(| (_, y) | *y <= y_max) {
+    group.append(
+      svg::node::element::Circle::new()
+        .set("cx", (level as f64 + 0.5) * space_per_level)
+        .set("cy", -(y / y_max) * face_height)
+        .set("r", 1.5)
+        .set("fill", "none")
+        .set("stroke", "black")
+    );
+  }
+
+  return group;
+}
+
+pub fn draw_scatter_plot(simulations: &Vec::<EnhancerSimulation>, path: &Path, log_y: bool) -> Result<(), PlotError> {
+  let v = scatter_plot_view(simulations, log_y);
+
+  // A page with a single view is then saved to an SVG file
+  return save_with_retry(&Page::single(&v), path, PLOT_SAVE_ATTEMPTS);
+}
+
+// p10, p50 and p90 lines over the scatter points, the median in a darker colour so it stands out
+// from the translucent cloud. Lines are broken at sparse levels rather than drawn through noise
+fn scatter_percentile_lines(simulations: &Vec::<EnhancerSimulation>, log_y: bool) -> Vec::<Plot> {
+  let mut levels = EnhancerSimulation::boxplot_data(simulations);
+  for samples in levels.iter_mut() {
+    samples.sort_by(f64::total_cmp);
+  }
+
+  let mut lines = Vec::<Plot>::new();
+  for (fraction, colour, width) in [(0.1, "#19CEA5FF", 1.0), (0.5, "#D62728FF", 2.0), (0.9, "#19CEA5FF", 1.0)] {
+    let mut segment = Vec::<(f64, f64)>::new();
+
+    for (level, samples) in levels.iter().enumerate() {
+      if samples.len() >= LevelStats::SPARSE_SAMPLE_COUNT {
+        let attempts = percentile(samples, fraction);
+        segment.push((level as f64, if log_y { log_attempts(attempts) } else { attempts }));
+        continue;
+      }
+
+      if segment.len() > 1 {
+        lines.push(Plot::new(segment).line_style(LineStyle::new().colour(colour).width(width)));
+      }
+      segment = Vec::new();
+    }
+
+    if segment.len() > 1 {
+      lines.push(Plot::new(segment).line_style(LineStyle::new().colour(colour).width(width)));
+    }
+  }
+
+  return lines;
+}
+
+fn scatter_plot_view(simulations: &Vec::<EnhancerSimulation>, log_y: bool) -> ContinuousView {
+  // Scatter plots expect a list of pairs
+  let mut history = EnhancerSimulation::scatterplot_data(simulations);
+  scatter_x_axis(&mut history);
+  let max_attempts = history.iter().fold(0.0, | max, point | f64::max(max, point.1));
+
+  if log_y {
+    for point in history.iter_mut() {
+      point.1 = log_attempts(point.1);
+    }
+  }
+
+  let (y_max, y_label) = attempts_axis(log_y, max_attempts);
+  let history_data = history;
+
+  // We create our scatter plot from the data
+  let scatter_plot: Plot = Plot::new(history_data).point_style(
+    PointStyle::new()
+      .marker(PointMarker::Square)
+      .colour("#19CEA540")
+      .size(0.5)
+  );
+
+  // The 'view' describes what set of data is drawn
+  let mut m_view = ContinuousView::new().add(scatter_plot);
+  for line in scatter_percentile_lines(simulations, log_y) {
+    m_view = m_view.add(line);
+  }
+
+  return m_view
+    .x_range(0.0, batch_max_level(simulations) as f64 + 1.0)
+    .y_range(0.0, y_max)
+    .x_label(level_axis_label(simulations))
+    .y_label(y_label);
+}
+
+fn histogram_view(simulations: &Vec::<EnhancerSimulation>) -> ContinuousView {
+  let attempts = completed_actors(simulations)
+    .map(| sim | sim.attempt_count as f64)
+    .collect::<Vec::<f64>>();
+
+  let histogram = Histogram::from_slice(&attempts, HistogramBins::Count(30))
+    .style(&BoxStyle::new().fill("#19CEA5FF"));
+
+  return ContinuousView::new()
+    .add(histogram)
+    .x_label("Total Attempts Taken To Reach Max Level")
+    .y_label("Actors");
+}
+
+// Draws the scatter plot, box plot and attempts-to-max histogram side by side in a single SVG.
+// Panels that have nothing meaningful to show, such as a box plot of a single level, are left out
+pub fn draw_dashboard(simulations: &Vec::<EnhancerSimulation>, path: &Path) -> Result<(), PlotError> {
+  let panel_width = 600;
+  let panel_height = 400;
+
+  // Same margins plotlib's `Page` uses around a single view
+  let x_margin = 120;
+  let y_margin = 60;
+  let x_offset = 0.6 * x_margin as f64;
+  let y_offset = 0.6 * y_margin as f64;
+
+  let level_count = simulations.iter().map(| sim | sim.history.len()).max().unwrap_or(0);
+
+  let mut views = Vec::<Box::<dyn View>>::new();
+  if !simulations.is_empty() {
+    views.push(Box::new(scatter_plot_view(simulations, false)));
+  }
+  if level_count > 1 {
+    views.push(Box::new(box_plot_view(simulations, false, &BoxPlotConfig::default()).0));
+  }
+  if completed_actors(simulations).next().is_some() {
+    views.push(Box::new(histogram_view(simulations)));
+  }
+
+  let width = panel_width * views.len().max(1) as u32;
+  let mut document = Document::new().set("viewBox", (0, 0, width, panel_height));
+
+  for (i, view) in views.iter().enumerate() {
+    let panel_x = (i as u32 * panel_width) as f64;
+    let group = view
+      .to_svg((panel_width - x_margin) as f64, (panel_height - y_margin) as f64)
+      .map_err(PlotError::render)?
+      .set("transform", format!("translate({}, {})", panel_x + x_offset, panel_height as f64 - y_offset));
+    document.append(group);
+  }
+
+  svg::save(path, &document)?;
+  return Ok(());
+}
+
+// plotlib has no area fill, so the p25-p75 band is shaded with closely spaced vertical strokes
+fn percentile_band(stats: &Vec::<LevelStats>) -> Vec::<Plot> {
+  let steps_per_level = 20;
+  let mut strokes = Vec::<Plot>::new();
+
+  for i in 1..stats.len() {
+    let from = &stats[i - 1];
+    let to = &stats[i];
+
+    for step in 0..steps_per_level {
+      let t = step as f64 / steps_per_level as f64;
+      let x = from.level as f64 + t;
+      let low = from.p25 + (to.p25 - from.p25) * t;
+      let high = from.p75 + (to.p75 - from.p75) * t;
+
+      strokes.push(Plot::new(vec![(x, low), (x, high)]).line_style(
+        LineStyle::new()
+          .colour("#19CEA530")
+          .width(3.0)
+      ));
+    }
+  }
+
+  return strokes;
+}
+
+pub fn draw_median_line_plot(simulations: &Vec::<EnhancerSimulation>, path: &Path) -> Result<(), PlotError> {
+  let stats = level_statistics(simulations);
+  let max_level = stats.len().saturating_sub(1) as f64;
+  let y_max = stats.iter().map(| level | level.p75).fold(1.0, f64::max) * 1.1;
+
+  let median_data = stats.iter()
+    .map(| level | (level.level as f64, level.median))
+    .collect::<Vec::<(f64, f64)>>();
+
+  // Levels reached by only a handful of actors are still drawn, but marked so they aren't over-read
+  let sparse_data = stats.iter()
+    .filter(| level | level.is_sparse())
+    .map(| level | (level.level as f64, level.median))
+    .collect::<Vec::<(f64, f64)>>();
+
+  let mut m_view = ContinuousView::new();
+
+  for stroke in percentile_band(&stats) {
+    m_view = m_view.add(stroke);
+  }
+
+  // plotlib can't render a line without points, an empty batch just gets the axes
+  if !median_data.is_empty() {
+    let median_line = Plot::new(median_data)
+      .line_style(LineStyle::new().colour("#0B6E58FF").width(2.0))
+      .point_style(PointStyle::new().marker(PointMarker::Circle).colour("#0B6E58FF").size(2.5))
+      .legend(String::from("Median"));
+    m_view = m_view.add(median_line);
+  }
+
+  if !sparse_data.is_empty() {
+    let sparse_points = Plot::new(sparse_data)
+      .point_style(PointStyle::new().marker(PointMarker::Cross).colour("#D62728FF").size(5.0))
+      .legend(format!("Fewer than {} samples", LevelStats::SPARSE_SAMPLE_COUNT));
+    m_view = m_view.add(sparse_points);
+  }
+
+  let view = m_view
+    .x_range(0.0, f64::max(1.0, max_level))
+    .y_range(0.0, y_max)
+    .x_label(level_axis_label(simulations))
+    .y_label("Median Attempts Taken To Reach (p25-p75 shaded)");
+
+  Page::single(&view).save(path).map_err(PlotError::render)?;
+  return Ok(());
+}
+
+pub fn draw_cdf_plot(simulations: &Vec::<EnhancerSimulation>, path: &Path) -> Result<(), PlotError> {
+  let cdf = attempts_cdf(simulations);
+  // A batch where every completed actor took 0 attempts still needs a non-empty range
+  let x_max = cdf.last().map_or(1.0, | point | f64::max(1.0, point.0));
+
+  // Without a single completed actor there is no line to draw, just the empty axes
+  let mut m_view = ContinuousView::new();
+  if !cdf.is_empty() {
+    let cdf_line = Plot::new(cdf)
+      .line_style(LineStyle::new().colour("#19CEA5FF").width(1.5));
+    m_view = m_view.add(cdf_line);
+  }
+
+  let view = m_view
+    .x_range(0.0, x_max)
+    .y_range(0.0, 1.0)
+    .x_label("Total Attempts Taken To Reach Max Level")
+    .y_label("Fraction Of Actors At Max Level");
+
+  Page::single(&view).save(path).map_err(PlotError::render)?;
+  return Ok(());
+}
+
+// Currency spent by each completed actor, in bins about `bin_width` wide. The bins evenly cover the
+// range of the data, so the width is rounded to fit a whole number of them. Panics if `bin_width`
+// isn't positive
+pub fn draw_cost_histogram(simulations: &Vec::<EnhancerSimulation>, bin_width: f64, path: &Path) -> Result<(), PlotError> {
+  assert!(bin_width > 0.0, "bin width must be positive, got {bin_width}");
+  check_cost_model(simulations)?;
+
+  let costs = completed_actors(simulations)
+    .map(| sim | sim.total_cost)
+    .collect::<Vec::<f64>>();
+  let min = costs.iter().fold(f64::INFINITY, | min, &x | f64::min(min, x));
+  let max = costs.iter().fold(f64::NEG_INFINITY, | max, &x | f64::max(max, x));
+  let bins = if costs.is_empty() { 1 } else { usize::max(1, ((max - min) / bin_width).ceil() as usize) };
+
+  // Without a single completed actor there are no bars to draw, just the empty axes
+  let mut m_view = ContinuousView::new();
+  if costs.is_empty() {
+    m_view = m_view.x_range(0.0, 1.0).y_range(0.0, 1.0);
+  } else {
+    let histogram = Histogram::from_slice(&costs, HistogramBins::Count(bins))
+      .style(&BoxStyle::new().fill("#19CEA5FF"));
+    m_view = m_view.add(histogram);
+  }
+
+  let view = m_view
+    .x_label("Total Cost To Reach Max Level (currency)")
+    .y_label("Actors");
+
+  Page::single(&view).save(path).map_err(PlotError::render)?;
+  return Ok(());
+}
+
+// Fraction of actors that reached max level within each total spend, e.g. to read off what 90% of
+// them spend at most
+pub fn draw_cost_cdf(simulations: &Vec::<EnhancerSimulation>, path: &Path) -> Result<(), PlotError> {
+  check_cost_model(simulations)?;
+
+  let cdf = cost_cdf(simulations);
+  let x_min = cdf.first().map_or(0.0, | point | point.0);
+  // Every actor spending the same still needs a non-empty range
+  let x_max = cdf.last().map_or(1.0, | point | f64::max(x_min + 1.0, point.0));
+
+  let mut m_view = ContinuousView::new();
+  if !cdf.is_empty() {
+    let cdf_line = Plot::new(cdf)
+      .line_style(LineStyle::new().colour("#19CEA5FF").width(1.5));
+    m_view = m_view.add(cdf_line);
+  }
+
+  let view = m_view
+    .x_range(x_min, x_max)
+    .y_range(0.0, 1.0)
+    .x_label("Total Cost To Reach Max Level (currency)")
+    .y_label("Fraction Of Actors At Max Level");
+
+  Page::single(&view).save(path).map_err(PlotError::render)?;
+  return Ok(());
+}
+
+// Plotting costs all at 0 would only hide that the cost model is switched off
+fn check_cost_model(simulations: &Vec::<EnhancerSimulation>) -> Result<(), PlotError> {
+  let has_costs = simulations.first().is_some_and(| sim | sim.rates.iter().any(| rate | rate.cost > 0.0));
+  if !has_costs {
+    return Err(PlotError::NoCostModel);
+  }
+
+  return Ok(());
+}
+
+// Median attempts per level of two batches on shared axes, e.g. two tuning candidates. The axes
+// cover whichever batch reaches further
+pub fn draw_comparison_plot(simulations_a: &Vec::<EnhancerSimulation>, label_a: &str, simulations_b: &Vec::<EnhancerSimulation>, label_b: &str, path: &Path) -> Result<(), PlotError> {
+  let stats_a = level_statistics(simulations_a);
+  let stats_b = level_statistics(simulations_b);
+
+  let max_level = usize::max(stats_a.len(), stats_b.len()).saturating_sub(1) as f64;
+  let y_max = stats_a.iter().chain(stats_b.iter()).map(| level | level.median).fold(1.0, f64::max) * 1.1;
+
+  let mut m_view = ContinuousView::new();
+  for (stats, label, colour) in [(&stats_a, label_a, "#0B6E58FF"), (&stats_b, label_b, "#FF7F0EFF")] {
+    if stats.is_empty() {
+      continue;
+    }
+
+    let median_data = stats.iter()
+      .map(| level | (level.level as f64, level.median))
+      .collect::<Vec::<(f64, f64)>>();
+
+    let median_line = Plot::new(median_data)
+      .line_style(LineStyle::new().colour(colour).width(2.0))
+      .point_style(PointStyle::new().marker(PointMarker::Circle).colour(colour).size(2.5))
+      .legend(String::from(label));
+    m_view = m_view.add(median_line);
+  }
+
+  let view = m_view
+    .x_range(0.0, f64::max(1.0, max_level))
+    .y_range(0.0, y_max)
+    .x_label("Enhancement Level")
+    .y_label("Median Attempts Taken To Reach");
+
+  Page::single(&view).save(path).map_err(PlotError::render)?;
+  return Ok(());
+}
+
+// Plots the output of `sweep`, points with an unreachable max level are left out
+pub fn draw_sweep_plot(points: &Vec::<(f32, f64)>, field: ParamField, path: &Path) -> Result<(), PlotError> {
+  let data = points.iter()
+    .filter(| point | point.1.is_finite())
+    .map(| point | (point.0 as f64, point.1))
+    .collect::<Vec::<(f64, f64)>>();
+
+  let mut x_min = data.iter().map(| point | point.0).fold(f64::INFINITY, f64::min);
+  let mut x_max = data.iter().map(| point | point.0).fold(f64::NEG_INFINITY, f64::max);
+  let y_max = data.iter().map(| point | point.1).fold(1.0, f64::max) * 1.1;
+
+  // Without a single finite point, or with just one, there is no range to derive from the data
+  if data.is_empty() {
+    x_min = 0.0;
+    x_max = 1.0;
+  } else if x_min == x_max {
+    x_min -= 0.5;
+    x_max += 0.5;
+  }
+
+  let mut m_view = ContinuousView::new();
+  if !data.is_empty() {
+    let sweep_line = Plot::new(data)
+      .line_style(LineStyle::new().colour("#19CEA5FF").width(1.5))
+      .point_style(PointStyle::new().marker(PointMarker::Circle).colour("#0B6E58FF").size(2.5));
+    m_view = m_view.add(sweep_line);
+  }
+
+  let view = m_view
+    .x_range(x_min, x_max)
+    .y_range(0.0, y_max)
+    .x_label(field.name())
+    .y_label("Mean Attempts Taken To Reach Max Level");
+
+  Page::single(&view).save(path).map_err(PlotError::render)?;
+  return Ok(());
+}
+
+// Mean attempts per level with 95% confidence interval error bars. Intervals flagged as unreliable
+// are drawn in red so sparse top levels don't suggest false confidence
+pub fn draw_confidence_plot(simulations: &Vec::<EnhancerSimulation>, path: &Path) -> Result<(), PlotError> {
+  let intervals = level_mean_intervals(simulations);
+  let max_level = intervals.len().saturating_sub(1) as f64;
+  let y_max = intervals.iter()
+    .map(| interval | if interval.upper.is_finite() { interval.upper } else { interval.mean })
+    .fold(1.0, f64::max) * 1.1;
+
+  let mut m_view = ContinuousView::new();
+
+  for interval in &intervals {
+    let colour = if interval.reliable { "#0B6E58FF" } else { "#D62728FF" };
+    let x = interval.level as f64;
+
+    if interval.upper.is_finite() {
+      let bar = Plot::new(vec![(x, f64::max(0.0, interval.lower)), (x, interval.upper)])
+        .line_style(LineStyle::new().colour(colour).width(1.5));
+      m_view = m_view.add(bar);
+    }
+
+    let mean = Plot::new(vec![(x, interval.mean)])
+      .point_style(PointStyle::new().marker(PointMarker::Circle).colour(colour).size(2.5));
+    m_view = m_view.add(mean);
+  }
+
+  let view = m_view
+    .x_range(-0.5, max_level + 0.5)
+    .y_range(0.0, y_max)
+    .x_label(level_axis_label(simulations))
+    .y_label("Mean Attempts Taken To Reach (95% CI)");
+
+  Page::single(&view).save(path).map_err(PlotError::render)?;
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env;
+  use std::fs;
+  use std::path::PathBuf;
+  use std::process;
+  use super::*;
+  use crate::params::{ default_params, generate_rates, EnhanceRateOverride, EnhancerParams };
+  use crate::testing::completed_batch;
+
+  // Unique per test and per process, so tests running in parallel don't write over each other
+  fn temp_svg(name: &str) -> PathBuf {
+    return env::temp_dir().join(format!("enhancer-{name}-{}.svg", process::id()));
+  }
+
+  #[test]
+  fn comparison_plot_contains_both_series() {
+    let rates_a = generate_rates(&default_params()).unwrap();
+    let rates_b = generate_rates(&EnhancerParams { upgrade_boost: 0.2, ..default_params() }).unwrap();
+    let simulations_a = completed_batch(&rates_a, 100, 1);
+    let simulations_b = completed_batch(&rates_b, 100, 1);
+
+    let path = temp_svg("comparison");
+    draw_comparison_plot(&simulations_a, "baseline", &simulations_b, "boosted", &path).unwrap();
+    let svg = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(svg.contains("baseline"));
+    assert!(svg.contains("boosted"));
+    assert!(svg.contains("#0B6E58FF") && svg.contains("#FF7F0EFF"));
+  }
+
+  #[test]
+  fn plots_draw_when_no_actor_reaches_the_top_levels() {
+    let mut params = default_params();
+    params.overrides.insert(6, EnhanceRateOverride { upgrade: Some(0.0), ..EnhanceRateOverride::default() });
+    let rates = generate_rates(&params).unwrap();
+    let mut simulations = EnhancerSimulation::create_many_seeded(&rates, 50, 1);
+    assert!(EnhancerSimulation::run_capped(&mut simulations, 300, | _progress | {}).is_err());
+
+    let path = temp_svg("unreachable");
+    draw_scatter_plot(&simulations, &path, false).unwrap();
+    draw_box_plot(&simulations, &path, true, &BoxPlotConfig::default()).unwrap();
+    draw_median_line_plot(&simulations, &path).unwrap();
+    draw_cdf_plot(&simulations, &path).unwrap();
+    draw_confidence_plot(&simulations, &path).unwrap();
+    draw_dashboard(&simulations, &path).unwrap();
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn scatter_view_has_the_points_and_three_percentile_lines() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let simulations = completed_batch(&rates, 100, 1);
+
+    assert_eq!(scatter_percentile_lines(&simulations, false).len(), 3);
+
+    let svg = Page::single(&scatter_plot_view(&simulations, false)).to_svg().unwrap().to_string();
+    // The points, drawn as squares
+    assert!(svg.contains("<rect"));
+    assert_eq!(svg.matches("<path").count(), 3);
+    assert!(svg.contains("#D62728FF"));
+  }
+
+  #[test]
+  fn box_plot_uses_the_configured_fill() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let simulations = completed_batch(&rates, 50, 1);
+    let config = BoxPlotConfig { fill: String::from("#1F77B4FF"), ..BoxPlotConfig::default() };
+
+    let (view, _outliers) = box_plot_view(&simulations, false, &config);
+    let svg = Page::single(&view).to_svg().unwrap().to_string();
+    assert!(svg.contains("#1F77B4FF"));
+    assert!(!svg.contains(&BoxPlotConfig::default().fill));
+  }
+
+  #[test]
+  fn out_of_range_whiskers_are_rejected() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let simulations = completed_batch(&rates, 50, 1);
+    let path = temp_svg("whiskers");
+
+    for (low, high) in [(-0.1, 0.9), (0.1, 1.5), (0.9, 0.1), (0.4, 0.6), (0.3, 0.9), (0.1, 0.7), (f64::NAN, 0.9)] {
+      let config = BoxPlotConfig { whiskers: Whiskers::Percentiles(low, high), ..BoxPlotConfig::default() };
+      let result = draw_box_plot(&simulations, &path, false, &config);
+      assert!(matches!(result, Err(PlotError::InvalidWhiskers(_, _))), "({low}, {high}) gave {result:?}");
+    }
+    assert!(!path.exists());
+
+    let config = BoxPlotConfig { whiskers: Whiskers::Percentiles(0.05, 0.95), show_outliers: true, ..BoxPlotConfig::default() };
+    draw_box_plot(&simulations, &path, false, &config).unwrap();
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn transient_save_failures_are_retried() {
+    let path = temp_svg("retry");
+    let mut calls = 0;
+
+    let result = retry_save(&path, 3, || {
+      calls += 1;
+      if calls <= 2 {
+        return Err(PlotError::Io(io::Error::from(io::ErrorKind::Interrupted)));
+      }
+      return Ok(());
+    });
+
+    assert!(result.is_ok());
+    assert_eq!(calls, 3);
+  }
+
+  #[test]
+  fn permanent_save_failures_are_not_retried() {
+    let path = temp_svg("no-retry");
+    let mut calls = 0;
+
+    let result = retry_save(&path, 3, || {
+      calls += 1;
+      return Err(PlotError::Io(io::Error::from(io::ErrorKind::PermissionDenied)));
+    });
+
+    assert!(matches!(result, Err(PlotError::Io(_))));
+    assert_eq!(calls, 1);
+  }
+
+  #[test]
+  fn jitter_stays_within_its_level_and_narrows_for_sparse_levels() {
+    // 1000 points at level 0, 10 at level 1
+    let mut points = (0..1010).map(| i | (if i < 1000 { 0.0 } else { 1.0 }, i as f64)).collect::<Vec::<_>>();
+    scatter_x_axis(&mut points);
+
+    let width = | level: f64 | points.iter()
+      .filter(| point | (point.0 - level).abs() < 0.5)
+      .map(| point | (point.0 - level).abs())
+      .fold(0.0, f64::max);
+
+    assert!(points.iter().all(| point | (point.0 - point.0.round()).abs() <= MAX_JITTER));
+    assert!(width(0.0) > 0.3);
+    assert!(width(1.0) < 0.2);
+  }
+
+  // plotlib's quartiles, the medians of the lower half, the whole and the upper half
+  fn quartiles(sorted: &[f64]) -> (f64, f64, f64) {
+    let median = | s: &[f64] | if s.len().is_multiple_of(2) { (s[s.len() / 2 - 1] + s[s.len() / 2]) / 2.0 } else { s[s.len() / 2] };
+    let half = sorted.len() / 2;
+    if half == 0 {
+      return (sorted[0], sorted[0], sorted[0]);
+    }
+    return (median(&sorted[..half]), median(sorted), median(&sorted[sorted.len() - half..]));
+  }
+
+  #[test]
+  fn custom_whiskers_leave_the_quartiles_alone() {
+    let config = BoxPlotConfig { whiskers: Whiskers::Percentiles(0.25, 0.75), ..BoxPlotConfig::default() };
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+
+    // Small counts are where the halves' medians sit furthest from the 25th and 75th percentiles
+    for n in 1..40 {
+      let mut samples = (0..n).map(| _ | rng.gen_range(0.0..100.0)).collect::<Vec::<f64>>();
+      samples.sort_by(f64::total_cmp);
+      let before = quartiles(&samples);
+
+      let outliers = clamp_to_whiskers(&mut samples, &config);
+      assert_eq!(quartiles(&samples), before, "{n} samples");
+      assert!(outliers.len() <= n);
+    }
+  }
+}
@@ -0,0 +1,384 @@
+use std::fmt;
+use std::io::{ self, IsTerminal };
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct EnhanceRate {
+  pub level: i32,
+  pub value: f32,
+  // Range the value is rolled in when an actor reaches this level, both equal `value` unless a
+  // value spread is configured
+  pub value_min: f32,
+  pub value_max: f32,
+  pub upgrade: f32,
+  pub great_success: f32,
+  pub downgrade: f32,
+  pub halve: f32,
+  pub reset: f32,
+  pub destroy: f32,
+  // Level a reset at this level drops to, 0 unless reset milestones are configured
+  pub reset_level: i32,
+  // Materials consumed by every attempt made at this level
+  pub materials: i32,
+  // Currency paid for every attempt made at this level
+  pub cost: f32,
+  // Wall-clock seconds every attempt made at this level takes
+  pub seconds: f32
+}
+
+// Layout of the rates table printed by `EnhanceRate::format_table_with`
+#[derive(Clone, Debug)]
+pub struct TableFormat {
+  // Decimal places of the probability columns
+  pub precision: usize,
+  // Widened automatically when `precision` wouldn't fit, see `effective_column_width`
+  pub column_width: usize,
+  pub level_width: usize,
+  pub separator_head: String,
+  pub separator_body: String,
+  // Wraps probability cells in ANSI colours, gains green and losses red by severity
+  pub colorize: bool
+}
+
+impl TableFormat {
+  // Width of the widest value a probability column can hold, "100.0%" at the default precision
+  fn required_column_width(&self) -> usize {
+    let fraction = if self.precision > 0 { 1 + self.precision } else { 0 };
+    return 3 + fraction + 1;
+  }
+
+  pub fn effective_column_width(&self) -> usize {
+    return usize::max(self.column_width, self.required_column_width());
+  }
+
+  const ANSI_RESET: &'static str = "\x1b[0m";
+
+  // Colours an already padded cell so the escape codes don't throw off the column widths
+  fn paint(&self, cell: String, colour: Option::<&str>) -> String {
+    return match colour {
+      Some(code) if self.colorize => format!("{code}{cell}{}", Self::ANSI_RESET),
+      _ => cell
+    };
+  }
+
+  fn gain_colour(rate: f32) -> Option::<&'static str> {
+    if rate <= 0.0 {
+      return None;
+    }
+
+    return Some(if rate >= 0.5 { "\x1b[1;32m" } else { "\x1b[32m" });
+  }
+
+  fn loss_colour(rate: f32) -> Option::<&'static str> {
+    if rate <= 0.0 {
+      return None;
+    }
+
+    return Some(if rate >= 0.1 { "\x1b[1;31m" } else if rate >= 0.01 { "\x1b[31m" } else { "\x1b[33m" });
+  }
+}
+
+impl Default for TableFormat {
+  fn default() -> Self {
+    return TableFormat {
+      precision: 1,
+      column_width: 1 + 5,
+      level_width: 3,
+      separator_head: String::from(" | "),
+      separator_body: String::from(" | "),
+      colorize: false
+    };
+  }
+}
+
+impl EnhanceRate {
+  pub fn no_change_rate(&self) -> f32 {
+    return 1.0 - (self.destroy + self.reset + self.halve + self.downgrade + self.upgrade + self.great_success);
+  }
+
+  // Copy of this rate with downgrade, halve and reset folded into no change
+  pub fn without_losses(&self) -> EnhanceRate {
+    return EnhanceRate { downgrade: 0.0, halve: 0.0, reset: 0.0, ..*self };
+  }
+
+  // Copy of this rate with `bonus` added to the upgrade rate. The bonus only takes from no change,
+  // so the probabilities never add up to more than 1
+  pub fn with_upgrade_bonus(&self, bonus: f32) -> EnhanceRate {
+    let bonus = f32::min(bonus, f32::max(0.0, self.no_change_rate()));
+    return EnhanceRate { upgrade: self.upgrade + bonus, ..*self };
+  }
+
+  // Probability of moving up at all, by one level or by a great success
+  pub fn advance_rate(&self) -> f32 {
+    return self.upgrade + self.great_success;
+  }
+
+  pub fn format_table(rates: &Vec::<EnhanceRate>) -> String {
+    return Self::format_table_with(rates, &TableFormat::default());
+  }
+
+  // `format_table` with colours, unless stdout is redirected
+  pub fn format_table_colored(rates: &Vec::<EnhanceRate>) -> String {
+    let format = TableFormat { colorize: io::stdout().is_terminal(), ..TableFormat::default() };
+    return Self::format_table_with(rates, &format);
+  }
+
+  pub fn format_table_with(rates: &Vec::<EnhanceRate>, format: &TableFormat) -> String {
+    let heading = Self::format_table_heading_with(format);
+    let rows = rates.iter()
+      .map(| rate | Self::format_table_row_with(rate, format))
+      .collect::<Vec::<String>>()
+      .concat();
+
+    return format!("{heading}{rows}");
+  }
+
+  pub fn format_table_heading() -> String {
+    return Self::format_table_heading_with(&TableFormat::default());
+  }
+
+  pub fn format_table_heading_with(format: &TableFormat) -> String {
+    let width = format.effective_column_width();
+    let level = format!("{:<1$}", "LVL", format.level_width);
+    let value = format!("{:<1$}", "VALUE", width);
+    let upgrade = format!("{:<1$}", "GAIN", width);
+    let great_success = format!("{:<1$}", "GREAT", width);
+    let no_change = format!("{:<1$}", "NONE", width);
+    let downgrade = format!("{:<1$}", "LOSE", width);
+    let halve = format!("{:<1$}", "HALVE", width);
+    let reset = format!("{:<1$}", "RESET", width);
+    let destroy = format!("{:<1$}", "BREAK", width);
+
+    return format!("{1}{0}{2}{0}{3}{0}{4}{0}{5}{0}{6}{0}{7}{0}{8}{0}{9}\n", format.separator_head, level, value, upgrade, great_success, no_change, downgrade, halve, reset, destroy);
+  }
+
+  pub fn format_table_row(rate: &EnhanceRate) -> String {
+    return Self::format_table_row_with(rate, &TableFormat::default());
+  }
+
+  pub fn format_table_row_with(rate: &EnhanceRate, format: &TableFormat) -> String {
+    let width = format.effective_column_width();
+    let precision = format.precision;
+    let level = format!("{:>1$}", rate.level, format.level_width);
+    let value = Self::format_rate_ex(rate.value * 100.0, 1, width);
+    let gain = | probability: f32 | format.paint(Self::format_rate_ex(probability * 100.0, precision, width), TableFormat::gain_colour(probability));
+    let loss = | probability: f32 | format.paint(Self::format_rate_ex(probability * 100.0, precision, width), TableFormat::loss_colour(probability));
+
+    let upgrade = gain(rate.upgrade);
+    let great_success = gain(rate.great_success);
+    let no_change = Self::format_rate_ex(rate.no_change_rate() * 100.0, precision, width);
+    let downgrade = loss(rate.downgrade);
+    let halve = loss(rate.halve);
+    let reset = loss(rate.reset);
+    let destroy = loss(rate.destroy);
+
+    return format!("{1}{0}{2}{0}{3}{0}{4}{0}{5}{0}{6}{0}{7}{0}{8}{0}{9}\n", format.separator_body, level, value, upgrade, great_success, no_change, downgrade, halve, reset, destroy);
+  }
+
+  // Rows whose largest change is at least this many probability points get marked in `diff_table`
+  pub const DIFF_HIGHLIGHT_THRESHOLD: f32 = 0.01;
+
+  fn diff_columns(rate: &EnhanceRate) -> [f32; 8] {
+    return [rate.value, rate.upgrade, rate.great_success, rate.no_change_rate(), rate.downgrade, rate.halve, rate.reset, rate.destroy];
+  }
+
+  // Per level change of every `format_table` column from `before` to `after`, in percentage points.
+  // Rows that changed by at least `DIFF_HIGHLIGHT_THRESHOLD` are marked with `*`, levels only one
+  // of the tables has are marked as added or removed
+  pub fn diff_table(before: &Vec::<EnhanceRate>, after: &Vec::<EnhanceRate>) -> String {
+    let format = TableFormat::default();
+    let width = usize::max(format.effective_column_width(), 7);
+    let separator = &format.separator_body;
+
+    let heading = ["LVL", "VALUE", "GAIN", "GREAT", "NONE", "LOSE", "HALVE", "RESET", "BREAK"].iter()
+      .enumerate()
+      .map(| (i, name) | format!("{:<1$}", name, if i == 0 { format.level_width } else { width }))
+      .collect::<Vec::<String>>()
+      .join(&format.separator_head);
+    let mut output = format!("{heading}\n");
+
+    for i in 0..usize::max(before.len(), after.len()) {
+      let level = format!("{:>1$}", i, format.level_width);
+
+      let (old, new) = match (before.get(i), after.get(i)) {
+        (Some(old), Some(new)) => (old, new),
+        (None, Some(_)) => {
+          output.push_str(&format!("{level}{separator}added\n"));
+          continue;
+        },
+        (Some(_), None) | (None, None) => {
+          output.push_str(&format!("{level}{separator}removed\n"));
+          continue;
+        }
+      };
+
+      let deltas = Self::diff_columns(old).iter()
+        .zip(Self::diff_columns(new).iter())
+        .map(| (a, b) | b - a)
+        .collect::<Vec::<f32>>();
+      let largest = deltas.iter().fold(0.0, | largest: f32, delta | largest.max(delta.abs()));
+
+      let cells = deltas.iter()
+        .map(| &delta | {
+          // Rounding noise shouldn't show up as a signed zero
+          let number = if delta.abs() < 5e-5 { String::from("0.0%") } else { format!("{:+.1}%", delta * 100.0) };
+          format!("{number:>width$}")
+        })
+        .collect::<Vec::<String>>()
+        .join(separator);
+      let marker = if largest >= Self::DIFF_HIGHLIGHT_THRESHOLD { " *" } else { "" };
+
+      output.push_str(&format!("{level}{separator}{cells}{marker}\n"));
+    }
+
+    return output;
+  }
+
+  // The same columns as `format_table`, as a GitHub flavoured Markdown table with right aligned
+  // numbers
+  pub fn format_table_markdown(rates: &Vec::<EnhanceRate>) -> String {
+    let mut output = String::from("| LVL | VALUE | GAIN | GREAT | NONE | LOSE | HALVE | RESET | BREAK |\n");
+    output.push_str("|---:|---:|---:|---:|---:|---:|---:|---:|---:|\n");
+
+    for rate in rates {
+      let columns = [rate.value, rate.upgrade, rate.great_success, rate.no_change_rate(), rate.downgrade, rate.halve, rate.reset, rate.destroy]
+        .iter()
+        .map(| &probability | format!("{:.1}%", probability * 100.0))
+        .collect::<Vec::<String>>();
+
+      output.push_str(&format!("| {} | {} |\n", rate.level, columns.join(" | ")));
+    }
+
+    return output;
+  }
+
+  const SPARKLINE_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+  // One block character per level showing `field` scaled between its own min and max, e.g.
+  // `EnhanceRate::sparkline(&rates, | rate | rate.upgrade)`. A field that is the same at every level
+  // is drawn as a flat line at mid height
+  pub fn sparkline(rates: &Vec::<EnhanceRate>, field: fn(&EnhanceRate) -> f32) -> String {
+    let values = rates.iter().map(field).collect::<Vec::<f32>>();
+    let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let top = Self::SPARKLINE_BLOCKS.len() - 1;
+
+    return values.iter()
+      .map(| &value | {
+        let index = if max > min {
+          (((value - min) / (max - min)) * top as f32).round() as usize
+        } else {
+          top / 2
+        };
+        Self::SPARKLINE_BLOCKS[usize::min(index, top)]
+      })
+      .collect();
+  }
+
+  pub fn format_rate(rate: f32) -> String {
+    let format = TableFormat::default();
+    return Self::format_rate_ex(rate * 100.0, format.precision, format.effective_column_width());
+  }
+
+  pub fn format_rate_ex(rate: f32, precision: usize, max_width: usize) -> String {
+    let number = format!("{rate:.precision$}%");
+    return format!("{number:>max_width$}");
+  }
+}
+
+// A level below max that actors can never leave upwards
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReachabilityError {
+  pub level: i32
+}
+
+impl fmt::Display for ReachabilityError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return write!(f, "upgrade probability is zero at level {}, max level is unreachable", self.level);
+  }
+}
+
+impl std::error::Error for ReachabilityError {}
+
+// Verifies every level below max has a nonzero advance probability, so progress is always possible
+pub fn rates_reachable(rates: &Vec::<EnhanceRate>) -> Result<(), ReachabilityError> {
+  let count = rates.len().saturating_sub(1);
+  for rate in &rates[..count] {
+    if rate.advance_rate() <= 0.0 {
+      return Err(ReachabilityError { level: rate.level });
+    }
+  }
+
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::params::{ default_params, generate_rates };
+
+  #[test]
+  fn markdown_table_rows_have_every_column() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let markdown = EnhanceRate::format_table_markdown(&rates);
+    let lines = markdown.lines().collect::<Vec::<&str>>();
+
+    assert_eq!(lines.len(), 2 + rates.len());
+    assert!(lines[1].trim_matches('|').split('|').all(| cell | cell == "---:"));
+    for line in lines {
+      assert_eq!(line.matches('|').count(), 10, "{line}");
+    }
+  }
+
+  #[test]
+  fn sparkline_has_a_block_per_level_and_peaks_at_the_highest_value() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let sparkline = EnhanceRate::sparkline(&rates, | rate | rate.downgrade).chars().collect::<Vec::<char>>();
+
+    assert_eq!(sparkline.len(), rates.len());
+    let highest = rates.iter().enumerate().max_by(| a, b | a.1.downgrade.total_cmp(&b.1.downgrade)).unwrap().0;
+    assert_eq!(sparkline[highest], '█');
+    // Level 0 can't downgrade, so it sits at the bottom
+    assert_eq!(sparkline[0], '▁');
+  }
+
+  #[test]
+  fn flat_sparkline_sits_at_mid_height() {
+    let rates = generate_rates(&default_params()).unwrap();
+    assert!(EnhanceRate::sparkline(&rates, | _rate | 0.5).chars().all(| block | block == '▄'));
+  }
+
+  #[test]
+  fn only_colored_tables_contain_ansi_codes() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let colored = EnhanceRate::format_table_with(&rates, &TableFormat { colorize: true, ..TableFormat::default() });
+    let plain = EnhanceRate::format_table_with(&rates, &TableFormat::default());
+
+    assert!(colored.contains("\x1b["));
+    assert!(!plain.contains('\x1b'));
+    assert_eq!(colored.lines().count(), plain.lines().count());
+  }
+
+  #[test]
+  fn diff_only_shows_the_changed_columns() {
+    let before = generate_rates(&default_params()).unwrap();
+    let mut after = before.clone();
+    after[4].upgrade += 0.05;
+
+    let diff = EnhanceRate::diff_table(&before, &after);
+    let rows = diff.lines().skip(1).collect::<Vec::<&str>>();
+    assert_eq!(rows.len(), before.len());
+
+    for (level, row) in rows.iter().enumerate() {
+      let cells = row.trim_end_matches(" *").split(" | ").skip(1).map(| cell | cell.trim()).collect::<Vec::<&str>>();
+      if level == 4 {
+        // Gain is taken out of no change, the other columns stay put
+        assert_eq!(cells, ["0.0%", "+5.0%", "0.0%", "-5.0%", "0.0%", "0.0%", "0.0%", "0.0%"]);
+        assert!(row.ends_with(" *"));
+      } else {
+        assert!(cells.iter().all(| &cell | cell == "0.0%"), "{row}");
+        assert!(!row.ends_with(" *"));
+      }
+    }
+  }
+}
@@ -0,0 +1,164 @@
+// Everything about a finished run in one serializable value, for tooling that would otherwise have
+// to scrape the log output and the individual CSVs
+use std::fs::File;
+use std::io::{ self, BufWriter, Write };
+use std::path::Path;
+use serde::Serialize;
+use crate::analysis::{ attempts_variance, destruction_probability, expected_attempts, expected_cost_by_level };
+use crate::params::{ generate_rates, EnhancerParams, InvalidParamError };
+use crate::rate::EnhanceRate;
+use crate::simulation::EnhancerSimulation;
+use crate::stats::{
+  abandon_statistics, attempt_gini, attempts_histogram, charge_statistics, completed_actors, cost_statistics,
+  destroy_statistics, level_statistics, material_statistics, mean_attempts, time_statistics, value_statistics,
+  AbandonStats, ChargeStats, CostStats, HistogramBin, LevelStats, MaterialStats, TimeStats, ValueStats
+};
+
+// Bins of the attempts to max histogram, the same as the histogram plot uses
+pub const REPORT_HISTOGRAM_BINS: usize = 30;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+  // Base seed the batch was created with, None if it wasn't recorded
+  pub seed: Option::<u64>,
+  pub params: EnhancerParams,
+  pub rates: Vec::<EnhanceRate>,
+  pub actors: usize,
+  pub completed: usize,
+  pub mean_attempts: f64,
+  pub attempt_gini: f64,
+  pub level_statistics: Vec::<LevelStats>,
+  pub attempts_histogram: Vec::<HistogramBin>,
+  pub abandoned: AbandonStats,
+  pub destroyed: AbandonStats,
+  pub cost: CostStats,
+  pub time: TimeStats,
+  pub charges: ChargeStats,
+  pub materials: MaterialStats,
+  pub values: ValueStats
+}
+
+impl Report {
+  // `seed` should be the one the batch was created with, e.g. by `create_many_seeded`
+  pub fn build(params: &EnhancerParams, rates: &Vec::<EnhanceRate>, simulations: &Vec::<EnhancerSimulation>, seed: Option::<u64>) -> Report {
+    return Report {
+      seed,
+      params: params.clone(),
+      rates: rates.clone(),
+      actors: simulations.len(),
+      completed: completed_actors(simulations).count(),
+      mean_attempts: mean_attempts(simulations),
+      attempt_gini: attempt_gini(simulations),
+      level_statistics: level_statistics(simulations),
+      attempts_histogram: attempts_histogram(simulations, REPORT_HISTOGRAM_BINS),
+      abandoned: abandon_statistics(simulations),
+      destroyed: destroy_statistics(simulations),
+      cost: cost_statistics(simulations),
+      time: time_statistics(simulations),
+      charges: charge_statistics(simulations),
+      materials: material_statistics(simulations),
+      values: value_statistics(simulations)
+    };
+  }
+
+  // Statistics that have no data, such as the mean of an empty batch, are written as null
+  pub fn write_json(&self, path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, self)?;
+    writeln!(writer)?;
+    return writer.flush();
+  }
+}
+
+// Quick look at a curve without simulating anything: the rates table followed by the analytical
+// expectations. Takes well under a millisecond, unlike a full batch
+pub fn preview(params: &EnhancerParams) -> Result<String, InvalidParamError> {
+  return Ok(preview_rates(&generate_rates(params)?));
+}
+
+// `preview` for an already generated rates table, e.g. one cut short at a target level
+pub fn preview_rates(rates: &Vec::<EnhanceRate>) -> String {
+  // The table already ends in a newline, which leaves a blank line before the expectations
+  let mut lines = vec![EnhanceRate::format_table(rates)];
+
+  let unit_costs = vec![1.0; rates.len()];
+  let attempts_by_level = expected_cost_by_level(rates, &unit_costs);
+  lines.push(String::from("LVL   EXPECTED ATTEMPTS TO REACH"));
+  for (level, attempts) in attempts_by_level.iter().enumerate() {
+    lines.push(format!("{:<5} {:.1}", level, attempts));
+  }
+  lines.push(String::new());
+
+  let mean = expected_attempts(rates);
+  if mean.is_finite() {
+    lines.push(format!("Expected attempts to max: {:.1} (sd {:.1})", mean, attempts_variance(rates).sqrt()));
+  } else {
+    lines.push(String::from("Expected attempts to max: max level is unreachable"));
+  }
+
+  let costs = rates.iter().map(| rate | rate.cost).collect::<Vec::<f32>>();
+  if costs.iter().any(| &cost | cost > 0.0) {
+    let cost_to_max = expected_cost_by_level(rates, &costs).last().copied().unwrap_or(0.0);
+    lines.push(format!("Expected cost to max: {:.1}", cost_to_max));
+  }
+
+  let destroyed = destruction_probability(rates);
+  if destroyed > 0.0 {
+    lines.push(format!("Chance of being destroyed before max: {:.2}%", destroyed * 100.0));
+  }
+
+  return lines.join("\n");
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env;
+  use std::fs;
+  use std::process;
+  use std::time::{ Duration, Instant };
+  use super::*;
+  use crate::params::default_params;
+  use crate::testing::completed_batch;
+
+  #[test]
+  fn written_report_has_every_section() {
+    let params = default_params();
+    let rates = generate_rates(&params).unwrap();
+    let simulations = completed_batch(&rates, 50, 1);
+    let path = env::temp_dir().join(format!("enhancer-report-{}.json", process::id()));
+
+    Report::build(&params, &rates, &simulations, Some(1)).write_json(&path).unwrap();
+    let json = serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&path).unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let keys = [
+      "seed", "params", "rates", "actors", "completed", "mean_attempts", "attempt_gini", "level_statistics",
+      "attempts_histogram", "abandoned", "destroyed", "cost", "time", "charges", "materials", "values"
+    ];
+    let object = json.as_object().unwrap();
+    assert_eq!(object.len(), keys.len());
+    for key in keys {
+      assert!(object.contains_key(key), "missing {key}");
+    }
+    assert_eq!(json["seed"], 1);
+    assert_eq!(json["actors"], 50);
+    assert_eq!(json["completed"], 50);
+  }
+
+  #[test]
+  fn preview_is_quick_and_covers_every_level() {
+    let params = default_params();
+
+    let start = Instant::now();
+    let output = preview(&params).unwrap();
+    // Generous so a loaded machine doesn't fail it, a simulated batch would take far longer
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    assert!(output.contains("EXPECTED ATTEMPTS TO REACH"));
+    assert!(output.contains("Expected attempts to max: "));
+    assert!(!output.contains("Expected cost to max"));
+    for level in 0..=params.max_level {
+      assert!(output.lines().any(| line | line.starts_with(&format!("{level:<5} "))), "no expectation for level {level}");
+    }
+  }
+}
@@ -0,0 +1,1408 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use log::trace;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use serde::{ Deserialize, Serialize };
+use crate::params::{ EnhanceRules, HalveRounding, InvalidParamError };
+use crate::rate::EnhanceRate;
+use crate::stats::RunningStats;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnhanceResult {
+  NoChange,
+  Upgrade,
+  GreatSuccess,
+  Downgrade,
+  Halve,
+  Reset,
+  // The item is gone, the actor can't make any more attempts
+  Destroy
+}
+
+impl EnhanceResult {
+  pub fn name(&self) -> &'static str {
+    match self {
+      EnhanceResult::NoChange => "no_change",
+      EnhanceResult::Upgrade => "upgrade",
+      EnhanceResult::GreatSuccess => "great_success",
+      EnhanceResult::Downgrade => "downgrade",
+      EnhanceResult::Halve => "halve",
+      EnhanceResult::Reset => "reset",
+      EnhanceResult::Destroy => "destroy"
+    }
+  }
+}
+
+// Returned when a string is not one of the `EnhanceResult::name`s
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnknownResultError {
+  pub text: String
+}
+
+impl fmt::Display for UnknownResultError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return write!(f, "unknown enhance result \"{}\"", self.text);
+  }
+}
+
+impl std::error::Error for UnknownResultError {}
+
+// Case insensitive, accepts the snake_case names from `EnhanceResult::name`
+impl FromStr for EnhanceResult {
+  type Err = UnknownResultError;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let results = [
+      EnhanceResult::NoChange,
+      EnhanceResult::Upgrade,
+      EnhanceResult::GreatSuccess,
+      EnhanceResult::Downgrade,
+      EnhanceResult::Halve,
+      EnhanceResult::Reset,
+      EnhanceResult::Destroy
+    ];
+
+    return results.into_iter()
+      .find(| result | result.name().eq_ignore_ascii_case(text.trim()))
+      .ok_or_else(|| UnknownResultError { text: String::from(text) });
+  }
+}
+
+// The rates are not serialized, a deserialized simulation has to be re-associated with a rates
+// table before use (see `load_checkpoint`)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EnhancerSimulation<'a> {
+  pub level: i32,
+  // Value rolled when the actor reached its current level, see `EnhanceRate::value_min`
+  #[serde(default)]
+  pub current_value: f32,
+  pub attempt_count: i32,
+  #[serde(default)]
+  pub materials_used: i64,
+  // Currency spent on attempts, and how much of it destroy refunds paid back
+  #[serde(default)]
+  pub total_cost: f64,
+  #[serde(default)]
+  pub refunded: f64,
+  // Currency spent on attempts made at each level, what a destroy refund is based on. Empty in
+  // checkpoints from before costs, `load_checkpoint` sizes it to the rates table
+  #[serde(default)]
+  pub cost_by_level: Vec::<f64>,
+  // Wall-clock seconds spent on attempts
+  #[serde(default)]
+  pub time_spent: f64,
+  #[serde(skip, default = "no_rates")]
+  pub rates: &'a Vec::<EnhanceRate>,
+  pub history: Vec::<i32>,
+  pub rng: ChaCha8Rng,
+  #[serde(default)]
+  pub rules: EnhanceRules,
+  // Whether the actor has ever been at or above `rules.protected_level`
+  #[serde(default = "reached_without_protection")]
+  pub reached_protected: bool,
+  // Set once the actor runs out of attempts under `rules.attempt_cap` without reaching max level
+  #[serde(default)]
+  pub abandoned: bool,
+  #[serde(default)]
+  pub destroyed: bool,
+  // Attempts left in the grace period started by the last reset or halve, see
+  // `rules.reset_grace_attempts`
+  #[serde(default)]
+  pub grace_remaining: i32,
+  // Protection charges left and how many this actor has burned, see `rules.protection_charges`
+  #[serde(default)]
+  pub charges_remaining: i32,
+  #[serde(default)]
+  pub charges_used: i32,
+  // Upgrade rate bonus built up by the current streak of successes, see `rules.momentum_per_success`
+  #[serde(default)]
+  pub momentum: f32,
+  // Failed attempts since the last success, see `rules.mercy_per_fail`
+  #[serde(default)]
+  pub fail_streak: i32,
+  // When set, the level after every attempt is appended to `trajectory`. Costs 4 bytes per attempt
+  // per actor, 10000 actors averaging 1000 attempts is already ~40MB, so it is off by default
+  #[serde(default)]
+  pub record_trajectory: bool,
+  #[serde(default)]
+  pub trajectory: Vec::<i32>,
+  // Temporary max level below the rates table's own, e.g. before an event lifts the cap. The actor
+  // counts as maxed once it gets there and resumes if the cap is raised. None uses the table's max
+  #[serde(default)]
+  pub level_cap: Option::<i32>
+}
+
+fn no_rates() -> &'static Vec::<EnhanceRate> {
+  static NO_RATES: Vec::<EnhanceRate> = Vec::new();
+  return &NO_RATES;
+}
+
+// Checkpoints from before protection have a protected level of 0, which every actor is at or above
+fn reached_without_protection() -> bool {
+  return true;
+}
+
+// Snapshot handed to the progress callback of `run_to_completion` after every iteration
+#[derive(Clone, Copy, Debug)]
+pub struct ProgressInfo {
+  pub iterations: i32,
+  // Abandoned and destroyed actors count as finished, they will not make any more attempts
+  pub maxed_fraction: f32,
+  // Mean attempts to max of the actors that have reached it so far, NaN before the first one does
+  pub running_mean: f64,
+  pub completed_count: usize
+}
+
+// Returned when a capped run gives up before every actor reached max level
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IterationCapError {
+  pub iterations: i32,
+  pub stuck_count: usize
+}
+
+impl fmt::Display for IterationCapError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return write!(f, "gave up after {} iterations with {} actors still below max level", self.iterations, self.stuck_count);
+  }
+}
+
+impl std::error::Error for IterationCapError {}
+
+// Where an actor with a fixed budget ended up, see `run_budget_limited`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BudgetedOutcome {
+  pub final_level: i32,
+  pub attempts: i32,
+  pub spent: f64,
+  // False if the actor got to max level (or was otherwise finished) before the budget ran out
+  pub ran_out: bool
+}
+
+// One line of `run_streaming_events`
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct StepEvent {
+  pub attempt: i32,
+  pub level: i32,
+  pub result: EnhanceResult
+}
+
+// Outcome of `run_until_converged`
+#[derive(Clone, Debug)]
+pub struct ConvergedRun<'a> {
+  pub simulations: Vec::<EnhancerSimulation<'a>>,
+  // Total actors simulated, a multiple of the batch size unless `max_actors` cut the last batch short
+  pub actors: i32,
+  // Running mean attempts to max after the last batch, over completed actors only
+  pub mean_attempts: f64,
+  // False if `max_actors` was hit before the mean settled
+  pub converged: bool
+}
+
+impl EnhancerSimulation<'_> {
+  pub fn boxplot_data(simulations: &Vec::<EnhancerSimulation>) -> Vec::<Vec::<f64>> {
+    let mut output = Vec::<Vec::<f64>>::new();
+
+    for sim in simulations {
+      let history = &sim.history;
+
+      for i in 0..history.len() {
+        if i == output.len() {
+          output.push(Vec::<f64>::new());
+        }
+
+        output[i].push(history[i] as f64);
+      }
+    }
+
+    return output;
+  }
+
+  pub fn scatterplot_data(simulations: &Vec::<EnhancerSimulation>) -> Vec::<(f64, f64)> {
+    let mut output = Vec::<(f64, f64)>::new();
+    for sim in simulations {
+      let history = &sim.history;
+      for i in 0..history.len() {
+        let x = i as f64;
+        let y = history[i] as f64;
+
+        output.push((x, y));
+      }
+    }
+
+    return output;
+  }
+
+  pub fn create_many(rates: &Vec::<EnhanceRate>, count: i32) -> Vec::<EnhancerSimulation<'_>> {
+    return Self::create_many_seeded(rates, count, thread_rng().gen::<u64>());
+  }
+
+  // Every actor gets its own generator, seeded from `seed` and its index only (see `actor_seed`),
+  // so a batch is reproducible no matter in which order or on which thread the actors are run
+  pub fn create_many_seeded(rates: &Vec::<EnhanceRate>, count: i32, seed: u64) -> Vec::<EnhancerSimulation<'_>> {
+    let mut output = Vec::<EnhancerSimulation>::with_capacity(count as usize);
+
+    for i in 0..count {
+      output.push(Self::create_seeded(rates, actor_seed(seed, i as u64)));
+    }
+
+    return output;
+  }
+
+  pub fn create(rates: &Vec::<EnhanceRate>) -> EnhancerSimulation<'_> {
+    return Self::create_with_rng(rates, ChaCha8Rng::from_entropy());
+  }
+
+  pub fn create_seeded(rates: &Vec::<EnhanceRate>, seed: u64) -> EnhancerSimulation<'_> {
+    return Self::create_with_rng(rates, ChaCha8Rng::seed_from_u64(seed));
+  }
+
+  // An actor that already holds an item at `start_level`, e.g. one bought rather than enhanced. The
+  // skipped levels count as reached after 0 attempts. Panics if `start_level` is outside
+  // [0, max level]
+  pub fn create_at(rates: &Vec::<EnhanceRate>, start_level: i32) -> EnhancerSimulation<'_> {
+    return Self::create(rates).started_at(start_level);
+  }
+
+  pub fn create_at_seeded(rates: &Vec::<EnhanceRate>, start_level: i32, seed: u64) -> EnhancerSimulation<'_> {
+    return Self::create_seeded(rates, seed).started_at(start_level);
+  }
+
+  fn started_at(mut self, start_level: i32) -> Self {
+    let max_level = self.rates.len() as i32 - 1;
+    assert!((0..=max_level).contains(&start_level), "start level must be within [0, {max_level}], got {start_level}");
+
+    self.level = start_level;
+    self.current_value = self.rates[start_level as usize].value;
+    self.history = vec![0; start_level as usize + 1];
+    return self;
+  }
+
+  // A batch of `total_actors` spread over starting levels by `start_distribution`, pairs of
+  // (level, weight) with weights summing to 1. Each level gets its share rounded so the counts add
+  // up to exactly `total_actors`, the leftover actors going to the levels with the largest
+  // remainders. Actors are ordered by their place in `start_distribution` and seeded as in
+  // `create_many_seeded`. Rejects a negative weight, weights not summing to 1 or a level outside
+  // [0, max level]
+  pub fn create_population<'a>(rates: &'a Vec::<EnhanceRate>, start_distribution: &[(i32, f64)], total_actors: i32, seed: u64) -> Result<Vec::<EnhancerSimulation<'a>>, InvalidParamError> {
+    const FIELD: &str = "start_distribution";
+    let max_level = rates.len() as i32 - 1;
+    for &(level, weight) in start_distribution {
+      if !(0..=max_level).contains(&level) {
+        return Err(InvalidParamError { field: FIELD, level: Some(level), value: level as f32, expected: "a level within [0, max_level]" });
+      }
+      if weight.is_nan() || weight < 0.0 {
+        return Err(InvalidParamError { field: FIELD, level: Some(level), value: weight as f32, expected: "a weight of at least 0" });
+      }
+    }
+    let total_weight = start_distribution.iter().map(| &(_, weight) | weight).sum::<f64>();
+    if (total_weight - 1.0).abs() > 1e-3 {
+      return Err(InvalidParamError { field: FIELD, level: None, value: total_weight as f32, expected: "weights summing to 1" });
+    }
+
+    let shares = start_distribution.iter()
+      .map(| &(_, weight) | weight / total_weight * total_actors as f64)
+      .collect::<Vec::<f64>>();
+    let mut counts = shares.iter().map(| share | share.floor() as i32).collect::<Vec::<i32>>();
+
+    let mut by_remainder = (0..shares.len()).collect::<Vec::<usize>>();
+    by_remainder.sort_by(| &a, &b | (shares[b] - shares[b].floor()).total_cmp(&(shares[a] - shares[a].floor())));
+    let leftover = total_actors - counts.iter().sum::<i32>();
+    for &i in by_remainder.iter().take(leftover.max(0) as usize) {
+      counts[i] += 1;
+    }
+
+    let mut output = Vec::<EnhancerSimulation>::with_capacity(total_actors as usize);
+    for (i, &(level, _)) in start_distribution.iter().enumerate() {
+      for _actor in 0..counts[i] {
+        let index = output.len() as u64;
+        output.push(Self::create_at_seeded(rates, level, actor_seed(seed, index)));
+      }
+    }
+
+    return Ok(output);
+  }
+
+  fn create_with_rng(rates: &Vec::<EnhanceRate>, rng: ChaCha8Rng) -> EnhancerSimulation<'_> {
+    let level = 0;
+    let count = 0;
+    let history = vec![0];
+    let rules = EnhanceRules::default();
+    return EnhancerSimulation {
+      level,
+      current_value: rates.first().map_or(0.0, | rate | rate.value),
+      attempt_count: count,
+      materials_used: 0,
+      total_cost: 0.0,
+      refunded: 0.0,
+      cost_by_level: vec![0.0; rates.len()],
+      time_spent: 0.0,
+      rates,
+      history,
+      rng,
+      rules,
+      reached_protected: true,
+      abandoned: false,
+      destroyed: false,
+      grace_remaining: 0,
+      charges_remaining: 0,
+      charges_used: 0,
+      momentum: 0.0,
+      fail_streak: 0,
+      record_trajectory: false,
+      trajectory: Vec::new(),
+      level_cap: None
+    };
+  }
+
+  // Replays a log of real attempts as an actor, so measured data can go through the same statistics
+  // and plots as simulated actors. The log is a CSV with one result per row (see
+  // `EnhanceResult::name`) in its first column and an optional `result` header. Costs and
+  // materials are charged from `rates` as if the attempts had been simulated
+  pub fn from_result_log<'a>(path: &Path, rates: &'a Vec::<EnhanceRate>) -> io::Result<EnhancerSimulation<'a>> {
+    let text = fs::read_to_string(path)?;
+    let mut sim = Self::create_with_rng(rates, ChaCha8Rng::seed_from_u64(0));
+
+    for (i, line) in text.lines().enumerate() {
+      let column = line.split(',').next().unwrap_or("").trim();
+      if column.is_empty() || (i == 0 && column.eq_ignore_ascii_case("result")) {
+        continue;
+      }
+
+      let invalid = | message: String | io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {message}", i + 1));
+      let result = column.parse::<EnhanceResult>().map_err(| error | invalid(error.to_string()))?;
+      if sim.is_finished() {
+        return Err(invalid(String::from("the log continues after the actor finished")));
+      }
+
+      sim.begin_attempt();
+      sim.finish_attempt(result);
+    }
+
+    return Ok(sim);
+  }
+
+  pub fn set_rules_many(simulations: &mut Vec::<EnhancerSimulation>, rules: EnhanceRules) {
+    for sim in simulations {
+      sim.set_rules(rules);
+    }
+  }
+
+  // Also refills the actor's protection charges
+  pub fn set_rules(&mut self, rules: EnhanceRules) {
+    self.rules = rules;
+    self.reached_protected = self.level >= rules.protected_level;
+    self.charges_remaining = rules.protection_charges;
+  }
+
+  pub fn set_record_trajectory_many(simulations: &mut Vec::<EnhancerSimulation>, record: bool) {
+    for sim in simulations {
+      sim.record_trajectory = record;
+    }
+  }
+
+  // Returns true if every actor in the set has reached max level or has been abandoned
+  pub fn enhance_many(simulations: &mut Vec::<EnhancerSimulation>) -> bool {
+    let mut all_maxed = true;
+
+    for sim in simulations {
+      if !sim.enhance() {
+        all_maxed = false;
+      }
+    }
+
+    return all_maxed;
+  }
+
+  // Enhances the set until every actor has reached max level, calling `on_progress` after each
+  // iteration. The final call always reports a maxed_fraction of 1.0.
+  // Returns the number of iterations taken
+  pub fn run_to_completion(simulations: &mut Vec::<EnhancerSimulation>, on_progress: impl FnMut(ProgressInfo)) -> i32 {
+    return Self::run_capped(simulations, i32::MAX, on_progress).unwrap_or_else(| error | error.iterations);
+  }
+
+  // Keeps enhancing while `budget` covers the next attempt, paying `costs[level]` before each one.
+  // `costs` is indexed by level like `EnhancerParams::cost_per_attempt`, levels past its end are
+  // free. A reset makes attempts cheap again, so an actor can still recover from one
+  pub fn run_with_budget(&mut self, costs: &Vec::<f32>, budget: f32) -> BudgetedOutcome {
+    let budget = budget as f64;
+    let mut spent = 0.0;
+    let mut ran_out = false;
+
+    while !self.is_finished() {
+      let cost = costs.get(self.level as usize).copied().unwrap_or(0.0) as f64;
+      if spent + cost > budget {
+        ran_out = true;
+        break;
+      }
+
+      spent += cost;
+      self.enhance();
+    }
+
+    return BudgetedOutcome { final_level: self.level, attempts: self.attempt_count, spent, ran_out };
+  }
+
+  // Gives every actor up to `attempts` more attempts, then stops whether or not it reached max.
+  // Actors that finish earlier simply stop there
+  pub fn run_for_attempts(simulations: &mut Vec::<EnhancerSimulation>, attempts: i32) {
+    for sim in simulations.iter_mut() {
+      for _i in 0..attempts {
+        if sim.enhance() {
+          break;
+        }
+      }
+    }
+  }
+
+  // Runs every actor to completion independently across threads. Actors don't interact, so the
+  // outcome matches `run_to_completion` for the same batch
+  pub fn run_parallel(simulations: &mut Vec::<EnhancerSimulation>) {
+    simulations.par_iter_mut().for_each(| sim | {
+      while !sim.enhance() {}
+    });
+  }
+
+  // Same as `run_to_completion`, but stops with an error once `max_iterations` is reached
+  pub fn run_capped(simulations: &mut Vec::<EnhancerSimulation>, max_iterations: i32, mut on_progress: impl FnMut(ProgressInfo)) -> Result<i32, IterationCapError> {
+    let actor_count = simulations.len();
+    let mut iterations = 0;
+    let mut all_maxed = false;
+
+    // Actors that start out at max never make an attempt, so they are counted up front
+    let mut running = RunningStats::default();
+    for sim in simulations.iter().filter(| sim | sim.is_maxed()) {
+      running.add(sim.attempt_count as f64);
+    }
+
+    while !all_maxed {
+      if iterations >= max_iterations {
+        let stuck_count = simulations.iter().filter(| sim | !sim.is_finished()).count();
+        return Err(IterationCapError { iterations, stuck_count });
+      }
+
+      iterations += 1;
+
+      let mut maxed_count = 0;
+      for sim in simulations.iter_mut() {
+        if sim.enhance() {
+          maxed_count += 1;
+        } else if sim.is_maxed() {
+          running.add(sim.attempt_count as f64);
+        }
+      }
+
+      all_maxed = maxed_count == actor_count;
+      let maxed_fraction = if all_maxed { 1.0 } else { maxed_count as f32 / actor_count as f32 };
+      trace!("Iteration {iterations}: {maxed_count} of {actor_count} actors finished");
+      on_progress(ProgressInfo {
+        iterations,
+        maxed_fraction,
+        running_mean: running.current_mean(),
+        completed_count: running.sample_count()
+      });
+    }
+
+    return Ok(iterations);
+  }
+
+  // Runs the batch while the level cap changes over time, `schedule` being (iteration, max level)
+  // pairs applied once that many iterations have run. Actors that have reached the current cap wait there
+  // and carry on once it is raised. If every actor is waiting with changes still to come, the run
+  // skips ahead to the next change. Returns the iterations taken once every actor is finished and
+  // the schedule is exhausted
+  pub fn run_with_schedule(simulations: &mut Vec::<EnhancerSimulation>, schedule: &[(i32, i32)]) -> i32 {
+    let mut changes = schedule.to_vec();
+    changes.sort_by_key(| &(iteration, _) | iteration);
+
+    let mut next_change = 0;
+    let mut iterations = 0;
+    loop {
+      while next_change < changes.len() && changes[next_change].0 <= iterations {
+        let cap = changes[next_change].1;
+        for sim in simulations.iter_mut() {
+          sim.level_cap = Some(cap);
+        }
+        next_change += 1;
+      }
+
+      if simulations.iter().all(| sim | sim.is_finished()) {
+        match changes.get(next_change) {
+          Some(&(iteration, _)) => {
+            iterations = iteration;
+            continue;
+          },
+          None => return iterations
+        }
+      }
+
+      iterations += 1;
+      Self::enhance_many(simulations);
+    }
+  }
+
+  // Simulates fresh actors `batch_size` at a time until the running mean attempts to max moves by
+  // less than `tolerance`, relative to the previous batch's mean, or `max_actors` is reached.
+  // Actors are seeded as in `create_many_seeded` with their index counting on across batches, so the
+  // run holds the same actors as a `create_many_seeded` batch of that size. Like `run_to_completion`
+  // this never returns if max level is unreachable and there is no attempt cap in `rules`
+  pub fn run_until_converged(rates: &Vec::<EnhanceRate>, batch_size: i32, tolerance: f64, max_actors: i32, seed: u64, rules: EnhanceRules) -> ConvergedRun<'_> {
+    let batch_size = i32::max(1, batch_size);
+    let mut simulations = Vec::<EnhancerSimulation>::new();
+    let mut total_attempts = 0.0;
+    let mut completed = 0;
+    let mut previous_mean = f64::NAN;
+    let mut mean = f64::NAN;
+    let mut converged = false;
+
+    while (simulations.len() as i32) < max_actors {
+      let first = simulations.len() as i32;
+      let count = i32::min(batch_size, max_actors - first);
+      let mut batch = (first..first + count)
+        .map(| i | Self::create_seeded(rates, actor_seed(seed, i as u64)))
+        .collect::<Vec::<EnhancerSimulation>>();
+      Self::set_rules_many(&mut batch, rules);
+      Self::run_to_completion(&mut batch, | _ | {});
+
+      for sim in batch.iter().filter(| sim | !sim.abandoned && !sim.destroyed) {
+        total_attempts += sim.attempt_count as f64;
+        completed += 1;
+      }
+      simulations.append(&mut batch);
+
+      mean = total_attempts / completed as f64;
+      let change = ((mean - previous_mean) / previous_mean).abs();
+      trace!("Ran {} actors, mean attempts {mean:.2}, relative change {change:.5}", simulations.len());
+
+      // NaN compares false, so a batch without a single completed actor never counts as converged
+      if change < tolerance {
+        converged = true;
+        break;
+      }
+      previous_mean = mean;
+    }
+
+    let actors = simulations.len() as i32;
+    return ConvergedRun { simulations, actors, mean_attempts: mean, converged };
+  }
+
+  pub fn is_maxed(&self) -> bool {
+    return self.level >= self.max_level();
+  }
+
+  // The rates table's max level, lowered by `level_cap` if one is set. Without any rates there is
+  // nothing to attempt, so the max is 0 and a fresh actor is already maxed
+  pub fn max_level(&self) -> i32 {
+    let table_max = self.rates.len() as i32 - 1;
+    return match self.level_cap {
+      Some(cap) => i32::min(table_max, cap),
+      None => table_max
+    };
+  }
+
+  // Maxed, abandoned or destroyed, either way the actor makes no more attempts
+  pub fn is_finished(&self) -> bool {
+    return self.abandoned || self.destroyed || self.is_maxed();
+  }
+
+  // Spent minus refunded
+  pub fn net_cost(&self) -> f64 {
+    return self.total_cost - self.refunded;
+  }
+
+  // Returns true if it has reached max level, has been abandoned or was destroyed
+  pub fn enhance(&mut self) -> bool {
+    if self.is_finished() {
+      return true;
+    }
+
+    let rate = self.begin_attempt();
+    let result = roll(rate, &mut UniformRoll(&mut self.rng));
+    self.finish_attempt(result);
+
+    return false;
+  }
+
+  // Same as `enhance`, but rolls come from `source` instead of the actor's own generator
+  pub fn enhance_with(&mut self, source: &mut impl RollSource) -> bool {
+    if self.is_finished() {
+      return true;
+    }
+
+    let rate = self.begin_attempt();
+    let result = roll(rate, source);
+    self.finish_attempt(result);
+
+    return false;
+  }
+
+  // Pays for the attempt and returns the rate it is rolled against
+  fn begin_attempt(&mut self) -> EnhanceRate {
+    let i = self.level as usize;
+    // During a grace period losses can't land, their probability goes to no change
+    let rate = if self.grace_remaining > 0 { self.rates[i].without_losses() } else { self.rates[i] };
+    self.grace_remaining = i32::max(0, self.grace_remaining - 1);
+    let mercy = self.rules.mercy_per_fail * self.fail_streak as f32;
+    let rate = rate.with_upgrade_bonus(self.momentum + mercy);
+
+    // Materials are paid up front at the level the attempt is made from
+    self.materials_used += rate.materials as i64;
+    self.total_cost += rate.cost as f64;
+    self.cost_by_level[i] += rate.cost as f64;
+    self.time_spent += rate.seconds as f64;
+
+    return rate;
+  }
+
+  // Returns the result that was actually applied, which is no change if a charge cancelled a loss
+  fn finish_attempt(&mut self, result: EnhanceResult) -> EnhanceResult {
+    let floor = if self.reached_protected { self.rules.protected_level } else { 0 };
+    let max_level = self.max_level();
+
+    // A charge is only spent on a loss that would actually take levels away
+    let is_loss = matches!(result, EnhanceResult::Downgrade | EnhanceResult::Halve | EnhanceResult::Reset);
+    let reset_level = self.rates[self.level as usize].reset_level;
+    let would_lose_levels = apply_result(self.level, result, floor, max_level, self.rules.halve_rounding, reset_level) < self.level;
+    let result = if is_loss && would_lose_levels && self.charges_remaining > 0 {
+      self.charges_remaining -= 1;
+      self.charges_used += 1;
+      EnhanceResult::NoChange
+    } else {
+      result
+    };
+
+    if result == EnhanceResult::Destroy {
+      self.destroy();
+    }
+
+    // A great success counts towards the streak like a regular upgrade
+    self.momentum = match result {
+      EnhanceResult::Upgrade | EnhanceResult::GreatSuccess => f32::min(self.rules.momentum_cap, self.momentum + self.rules.momentum_per_success),
+      _ => 0.0
+    };
+    self.fail_streak = match result {
+      EnhanceResult::Upgrade | EnhanceResult::GreatSuccess => 0,
+      _ => self.fail_streak + 1
+    };
+
+    // A new reset restarts the grace period, it doesn't add to what is left of the old one
+    if result == EnhanceResult::Reset || result == EnhanceResult::Halve {
+      self.grace_remaining = self.rules.reset_grace_attempts;
+    }
+
+    let level = apply_result(self.level, result, floor, max_level, self.rules.halve_rounding, reset_level);
+    let attempt_count = self.attempt_count + 1;
+
+    if level != self.level {
+      self.current_value = self.roll_value(level);
+    }
+
+    self.level = level;
+    self.attempt_count = attempt_count;
+
+    if self.record_trajectory {
+      self.trajectory.push(level);
+    }
+
+    if level >= self.rules.protected_level {
+      self.reached_protected = true;
+    }
+
+    if let Some(attempt_cap) = self.rules.attempt_cap {
+      if attempt_count >= attempt_cap && !self.is_maxed() && !self.destroyed {
+        self.abandoned = true;
+      }
+    }
+
+    // A great success can skip a level, which then counts as reached on the same attempt
+    while level as usize >= self.history.len() {
+      self.history.push(attempt_count);
+    }
+
+    return result;
+  }
+
+  // Only draws from the generator when the level actually has a range, so runs without a value
+  // spread roll exactly as they did before it existed
+  fn roll_value(&mut self, level: i32) -> f32 {
+    let rate = &self.rates[level as usize];
+    if rate.value_max > rate.value_min {
+      return self.rng.gen_range(rate.value_min..=rate.value_max);
+    }
+
+    return rate.value;
+  }
+
+  fn destroy(&mut self) {
+    let spent_at_level = self.cost_by_level[self.level as usize];
+    let refund = spent_at_level * self.rules.destroy_refund_ratio as f64;
+
+    self.destroyed = true;
+    self.refunded = f64::min(self.total_cost, self.refunded + refund);
+  }
+}
+
+// A single actor started from `seed`, run until it can't afford another attempt
+pub fn run_budget_limited(rates: &Vec::<EnhanceRate>, costs: &Vec::<f32>, budget: f32, seed: u64) -> BudgetedOutcome {
+  return EnhancerSimulation::create_seeded(rates, seed).run_with_budget(costs, budget);
+}
+
+// Distribution of outcomes for `actors` actors with the same budget each, seeded as in
+// `create_many_seeded`
+pub fn run_budget_limited_many(rates: &Vec::<EnhanceRate>, costs: &Vec::<f32>, budget: f32, actors: i32, seed: u64) -> Vec::<BudgetedOutcome> {
+  return EnhancerSimulation::create_many_seeded(rates, actors, seed)
+    .iter_mut()
+    .map(| sim | sim.run_with_budget(costs, budget))
+    .collect();
+}
+
+// Runs `simulation` until it is finished, writing each attempt to `writer` as a line of JSON as
+// soon as it happens, so a consumer can tail the output live
+pub fn run_streaming_events(simulation: &mut EnhancerSimulation, writer: &mut impl Write) -> io::Result<()> {
+  while !simulation.is_finished() {
+    let rate = simulation.begin_attempt();
+    let result = roll(rate, &mut UniformRoll(&mut simulation.rng));
+    let result = simulation.finish_attempt(result);
+
+    let event = StepEvent {
+      attempt: simulation.attempt_count,
+      level: simulation.level,
+      result
+    };
+    serde_json::to_writer(&mut *writer, &event)?;
+    writeln!(writer)?;
+    writer.flush()?;
+  }
+
+  return Ok(());
+}
+
+// Seed of actor `index` in a batch seeded with `base_seed`. This is the `index`-th output of a
+// splitmix64 sequence started at `base_seed`, which spreads neighbouring indices well apart
+pub fn actor_seed(base_seed: u64, index: u64) -> u64 {
+  let mut z = base_seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9E3779B97F4A7C15));
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+  return z ^ (z >> 31);
+}
+
+// Outcomes in the order their regions are laid out over [0, 1), no change takes whatever is left
+const ROLL_ORDER: [EnhanceResult; 7] = [
+  EnhanceResult::Destroy,
+  EnhanceResult::Reset,
+  EnhanceResult::Halve,
+  EnhanceResult::Downgrade,
+  EnhanceResult::Upgrade,
+  EnhanceResult::GreatSuccess,
+  EnhanceResult::NoChange
+];
+
+// Upper bound of each outcome's region in `ROLL_ORDER`. Summed in f64 so the no change region
+// doesn't pick up f32 rounding from adding several small probabilities
+pub fn cumulative_distribution(rate: &EnhanceRate) -> [f64; 7] {
+  let probabilities = [rate.destroy, rate.reset, rate.halve, rate.downgrade, rate.upgrade, rate.great_success];
+  let mut output = [1.0; 7];
+
+  let mut total = 0.0;
+  for i in 0..probabilities.len() {
+    total += probabilities[i] as f64;
+    output[i] = total;
+  }
+  output[6] = f64::max(1.0, total);
+
+  return output;
+}
+
+// Where the uniform-looking value behind every roll comes from. Values must be in [0, 1), see
+// `roll_with` for what happens otherwise. Swapping the source for a non-uniform one changes how
+// lucky actors are without touching the rates
+pub trait RollSource {
+  fn next_roll(&mut self) -> f32;
+}
+
+// The default source, a flat distribution drawn from any generator
+pub struct UniformRoll<R: Rng>(pub R);
+
+impl UniformRoll<ThreadRng> {
+  pub fn from_thread_rng() -> UniformRoll<ThreadRng> {
+    return UniformRoll(thread_rng());
+  }
+}
+
+impl<R: Rng> RollSource for UniformRoll<R> {
+  fn next_roll(&mut self) -> f32 {
+    return self.0.gen::<f32>();
+  }
+}
+
+// What `ScriptedRolls` does once every value has been used
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ScriptEnd {
+  // Running out means the script didn't cover the run it was written for
+  #[default]
+  Panic,
+  // Starts over from the first value
+  Cycle
+}
+
+// A fixed sequence of roll values handed out in order, for runs whose every outcome is known up
+// front regardless of the generator implementation
+#[derive(Clone, Debug)]
+pub struct ScriptedRolls {
+  rolls: Vec::<f32>,
+  position: usize,
+  on_end: ScriptEnd
+}
+
+impl ScriptedRolls {
+  pub fn new(rolls: Vec::<f32>, on_end: ScriptEnd) -> ScriptedRolls {
+    return ScriptedRolls { rolls, position: 0, on_end };
+  }
+
+  // Values handed out so far, counting every pass of a cycling script
+  pub fn used(&self) -> usize {
+    return self.position;
+  }
+}
+
+impl RollSource for ScriptedRolls {
+  fn next_roll(&mut self) -> f32 {
+    let length = self.rolls.len();
+    if self.position >= length && (self.on_end == ScriptEnd::Panic || length == 0) {
+      panic!("scripted rolls ran out after {length} values");
+    }
+
+    let value = self.rolls[self.position % length];
+    self.position += 1;
+    return value;
+  }
+}
+
+pub fn roll(rate: EnhanceRate, source: &mut impl RollSource) -> EnhanceResult {
+  return roll_with(&rate, source.next_roll());
+}
+
+// Maps a uniform value in [0, 1) onto an outcome. A value of 1.0 or above, which a generator
+// shouldn't produce, lands on the last outcome that has any probability instead of past the end
+pub fn roll_with(rate: &EnhanceRate, value: f32) -> EnhanceResult {
+  let value = value as f64;
+  let cumulative = cumulative_distribution(rate);
+
+  let mut lower = 0.0;
+  let mut last_possible = EnhanceResult::NoChange;
+  for i in 0..cumulative.len() {
+    let upper = cumulative[i];
+    if value < upper {
+      return ROLL_ORDER[i];
+    }
+
+    if upper > lower {
+      last_possible = ROLL_ORDER[i];
+    }
+    lower = upper;
+  }
+
+  return last_possible;
+}
+
+// Losses never take the level below `floor`, a reset lands on `reset_level` or `floor` if that is
+// higher, and gains never go past `max_level`. A destroy leaves the level alone, the actor is marked as destroyed instead
+pub fn apply_result(level: i32, result: EnhanceResult, floor: i32, max_level: i32, halve_rounding: HalveRounding, reset_level: i32) -> i32 {
+  match result {
+    EnhanceResult::NoChange | EnhanceResult::Destroy => level,
+    EnhanceResult::Downgrade => i32::max(floor, level - 1),
+    EnhanceResult::Halve => i32::max(floor, halve_rounding.halve(level)),
+    EnhanceResult::Reset => i32::max(floor, reset_level),
+    EnhanceResult::Upgrade => level + 1,
+    EnhanceResult::GreatSuccess => i32::min(max_level, level + 2)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env;
+  use std::process;
+  use super::*;
+  use crate::params::{ default_params, generate_rates, EnhanceRateOverride, EnhancerParams };
+  use crate::stats::{ abandon_statistics, mean_attempts, percentile };
+  use crate::testing::{ completed_batch, seeded_mean_attempts };
+
+  #[test]
+  fn great_successes_reach_max_in_fewer_attempts_than_levels() {
+    let params = EnhancerParams {
+      min_upgrade_rate: 1.0,
+      great_success_ratio: 1.0,
+      max_downgrade_rate: 0.0,
+      ..default_params()
+    };
+    let rates = generate_rates(&params).unwrap();
+    let mut sim = EnhancerSimulation::create_seeded(&rates, 1);
+    while !sim.enhance() {}
+
+    assert!(sim.is_maxed());
+    assert!(sim.attempt_count < params.max_level, "took {} attempts for {} levels", sim.attempt_count, params.max_level);
+    // Skipped levels count as reached on the attempt that jumped over them
+    assert_eq!(sim.history, vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5]);
+  }
+
+  #[test]
+  fn materials_cover_every_attempt() {
+    let params = EnhancerParams { materials_per_attempt: vec![1, 1, 2, 2, 3, 3, 4, 4, 5, 5], ..default_params() };
+    let rates = generate_rates(&params).unwrap();
+    let simulations = completed_batch(&rates, 200, 1);
+
+    for sim in &simulations {
+      assert!(sim.materials_used >= sim.attempt_count as i64);
+    }
+  }
+
+  #[test]
+  fn attempt_cap_abandons_every_actor_on_an_impossible_curve() {
+    let mut params = default_params();
+    params.attempt_cap = Some(200);
+    params.overrides.insert(3, EnhanceRateOverride { upgrade: Some(0.0), ..EnhanceRateOverride::default() });
+    let rates = generate_rates(&params).unwrap();
+
+    let mut simulations = EnhancerSimulation::create_many_seeded(&rates, 100, 1);
+    EnhancerSimulation::set_rules_many(&mut simulations, EnhanceRules::from_params(&params));
+    let result = EnhancerSimulation::run_capped(&mut simulations, 10_000, | _progress | {});
+
+    // Every actor is abandoned on its 200th attempt, the next iteration finds them all finished
+    assert_eq!(result, Ok(201));
+    assert_eq!(abandon_statistics(&simulations).fraction, 1.0);
+  }
+
+  #[test]
+  fn full_refund_leaves_destroyed_actors_without_net_cost() {
+    let mut params = EnhancerParams { max_level: 2, max_downgrade_rate: 0.2, cost_per_attempt: vec![0.0, 5.0], destroy_refund_ratio: 1.0, ..default_params() };
+    params.overrides.insert(1, EnhanceRateOverride { destroy: Some(0.3), ..EnhanceRateOverride::default() });
+    let rates = generate_rates(&params).unwrap();
+
+    let mut simulations = EnhancerSimulation::create_many_seeded(&rates, 200, 1);
+    EnhancerSimulation::set_rules_many(&mut simulations, EnhanceRules::from_params(&params));
+    EnhancerSimulation::run_to_completion(&mut simulations, | _progress | {});
+
+    let destroyed = simulations.iter().filter(| sim | sim.destroyed).collect::<Vec::<&EnhancerSimulation>>();
+    assert!(!destroyed.is_empty());
+    for sim in destroyed {
+      assert!(sim.total_cost > 0.0);
+      assert_eq!(sim.net_cost(), 0.0);
+    }
+  }
+
+  #[test]
+  fn convergence_stops_early_on_a_gentle_curve() {
+    let params = EnhancerParams { min_upgrade_rate: 1.0, max_downgrade_rate: 0.0, ..default_params() };
+    let rates = generate_rates(&params).unwrap();
+    let run = EnhancerSimulation::run_until_converged(&rates, 50, 1e-3, 5000, 1, EnhanceRules::default());
+
+    assert!(run.converged);
+    assert_eq!(run.actors, 100);
+    assert_eq!(run.mean_attempts, 10.0);
+  }
+
+  #[test]
+  fn convergence_runs_to_the_cap_on_a_swingy_curve() {
+    let params = EnhancerParams { max_downgrade_rate: 0.3, reset_ratio: 0.5, min_reset_level: 1, ..default_params() };
+    let rates = generate_rates(&params).unwrap();
+    let run = EnhancerSimulation::run_until_converged(&rates, 50, 1e-6, 300, 1, EnhanceRules::default());
+
+    assert!(!run.converged);
+    assert_eq!(run.actors, 300);
+  }
+
+  #[test]
+  fn converged_run_matches_a_seeded_batch_of_the_same_size() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let run = EnhancerSimulation::run_until_converged(&rates, 40, 0.05, 2000, 3, EnhanceRules::default());
+    let batch = completed_batch(&rates, run.actors, 3);
+
+    assert!(run.actors > 40);
+    for (a, b) in run.simulations.iter().zip(batch.iter()) {
+      assert_eq!(a.attempt_count, b.attempt_count);
+      assert_eq!(a.history, b.history);
+    }
+    assert_eq!(run.mean_attempts, mean_attempts(&batch));
+  }
+
+  #[test]
+  fn trajectory_records_every_attempt() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let mut simulations = EnhancerSimulation::create_many_seeded(&rates, 50, 1);
+    EnhancerSimulation::set_record_trajectory_many(&mut simulations, true);
+    EnhancerSimulation::run_to_completion(&mut simulations, | _progress | {});
+
+    for sim in &simulations {
+      assert_eq!(sim.trajectory.len(), sim.attempt_count as usize);
+      assert_eq!(sim.trajectory.last().copied(), Some(sim.level));
+    }
+  }
+
+  fn rate_with(destroy: f32, reset: f32, halve: f32, downgrade: f32, upgrade: f32, great_success: f32) -> EnhanceRate {
+    return EnhanceRate {
+      level: 5,
+      value: 1.0,
+      value_min: 1.0,
+      value_max: 1.0,
+      upgrade,
+      great_success,
+      downgrade,
+      halve,
+      reset,
+      destroy,
+      reset_level: 0,
+      materials: 0,
+      cost: 0.0,
+      seconds: 0.0
+    };
+  }
+
+  fn just_below(value: f32) -> f32 {
+    return f32::from_bits(value.to_bits() - 1);
+  }
+
+  #[test]
+  fn roll_regions_are_exactly_sized() {
+    let rate = rate_with(0.125, 0.125, 0.125, 0.125, 0.25, 0.125);
+    assert_eq!(cumulative_distribution(&rate), [0.125, 0.25, 0.375, 0.5, 0.75, 0.875, 1.0]);
+
+    let boundaries = [0.0, 0.125, 0.25, 0.375, 0.5, 0.75, 0.875];
+    for (i, &lower) in boundaries.iter().enumerate() {
+      assert_eq!(roll_with(&rate, lower), ROLL_ORDER[i]);
+      if lower > 0.0 {
+        assert_eq!(roll_with(&rate, just_below(lower)), ROLL_ORDER[i - 1]);
+      }
+    }
+    assert_eq!(roll_with(&rate, just_below(1.0)), EnhanceResult::NoChange);
+  }
+
+  #[test]
+  fn out_of_range_rolls_land_on_the_last_possible_outcome() {
+    let certain = rate_with(0.0, 0.0, 0.0, 0.5, 0.5, 0.0);
+    assert_eq!(roll_with(&certain, 1.0), EnhanceResult::Upgrade);
+    assert_eq!(roll_with(&certain, 0.5), EnhanceResult::Upgrade);
+    assert_eq!(roll_with(&certain, just_below(0.5)), EnhanceResult::Downgrade);
+
+    let with_no_change = rate_with(0.0, 0.0, 0.0, 0.25, 0.25, 0.0);
+    assert_eq!(roll_with(&with_no_change, 1.0), EnhanceResult::NoChange);
+  }
+
+  #[test]
+  fn parallel_run_matches_serial_run() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let mut serial = EnhancerSimulation::create_many_seeded(&rates, 200, 11);
+    let mut parallel = EnhancerSimulation::create_many_seeded(&rates, 200, 11);
+
+    EnhancerSimulation::run_to_completion(&mut serial, | _progress | {});
+    EnhancerSimulation::run_parallel(&mut parallel);
+
+    for (a, b) in serial.iter().zip(parallel.iter()) {
+      assert_eq!(a.history, b.history);
+    }
+  }
+
+  #[test]
+  fn flat_duration_times_attempts_is_the_time_spent() {
+    let params = EnhancerParams { seconds_per_attempt: 2.5, ..default_params() };
+    let rates = generate_rates(&params).unwrap();
+    let simulations = completed_batch(&rates, 100, 1);
+
+    for sim in &simulations {
+      assert_eq!(sim.time_spent, sim.attempt_count as f64 * 2.5);
+    }
+  }
+
+  #[test]
+  fn grace_period_blocks_losses_after_a_reset_or_halve() {
+    let params = EnhancerParams { reset_grace_attempts: 3, ..default_params() };
+    let rates = generate_rates(&params).unwrap();
+    let mut simulations = EnhancerSimulation::create_many_seeded(&rates, 100, 1);
+    EnhancerSimulation::set_rules_many(&mut simulations, EnhanceRules::from_params(&params));
+    EnhancerSimulation::set_record_trajectory_many(&mut simulations, true);
+    EnhancerSimulation::run_to_completion(&mut simulations, | _progress | {});
+
+    let mut big_drops = 0;
+    for sim in &simulations {
+      let levels = [vec![0], sim.trajectory.clone()].concat();
+      for i in 1..levels.len() {
+        // A downgrade only ever costs one level, anything more was a halve or a reset
+        if levels[i - 1] - levels[i] < 2 {
+          continue;
+        }
+
+        big_drops += 1;
+        let grace_end = usize::min(levels.len(), i + 1 + params.reset_grace_attempts as usize);
+        for j in (i + 1)..grace_end {
+          assert!(levels[j] >= levels[j - 1], "lost a level {} attempts after a reset", j - i);
+        }
+      }
+    }
+    assert!(big_drops > 0);
+  }
+
+  #[test]
+  fn momentum_reaches_max_faster() {
+    let base = default_params();
+    let momentum = EnhancerParams { momentum_per_success: 0.1, momentum_cap: 0.3, ..default_params() };
+
+    assert!(seeded_mean_attempts(&momentum, 500, 1) < seeded_mean_attempts(&base, 500, 1));
+  }
+
+  #[test]
+  fn tiny_budget_ends_at_a_low_level() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let costs = vec![10.0; 10];
+
+    for outcome in run_budget_limited_many(&rates, &costs, 50.0, 100, 1) {
+      assert!(outcome.ran_out);
+      assert_eq!(outcome.attempts, 5);
+      assert!(outcome.spent <= 50.0);
+      assert!(outcome.final_level <= 5);
+    }
+
+    let generous = run_budget_limited(&rates, &costs, 1e9, 1);
+    assert!(!generous.ran_out);
+    assert_eq!(generous.final_level, 10);
+  }
+
+  struct ConstantRoll(f32);
+
+  impl RollSource for ConstantRoll {
+    fn next_roll(&mut self) -> f32 {
+      return self.0;
+    }
+  }
+
+  #[test]
+  fn zero_roll_always_lands_on_the_first_possible_outcome() {
+    let params = EnhancerParams { destroy_ratio: 0.1, ..default_params() };
+    let rates = generate_rates(&params).unwrap();
+
+    for rate in &rates {
+      let probabilities = [rate.destroy, rate.reset, rate.halve, rate.downgrade, rate.upgrade, rate.great_success];
+      let first = probabilities.iter().position(| &p | p > 0.0).map_or(EnhanceResult::NoChange, | i | ROLL_ORDER[i]);
+      for _attempt in 0..10 {
+        assert_eq!(roll(*rate, &mut ConstantRoll(0.0)), first, "level {}", rate.level);
+      }
+    }
+    assert_eq!(roll(rates[9], &mut ConstantRoll(0.0)), EnhanceResult::Destroy);
+    assert_eq!(roll(rates[0], &mut ConstantRoll(0.0)), EnhanceResult::Upgrade);
+  }
+
+  #[test]
+  fn replayed_log_matches_applying_the_results_by_hand() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let results = [
+      EnhanceResult::Upgrade, EnhanceResult::Upgrade, EnhanceResult::Upgrade, EnhanceResult::NoChange,
+      EnhanceResult::Halve, EnhanceResult::Upgrade, EnhanceResult::GreatSuccess, EnhanceResult::Downgrade,
+      EnhanceResult::Upgrade, EnhanceResult::Upgrade, EnhanceResult::Reset, EnhanceResult::Upgrade
+    ];
+
+    let path = env::temp_dir().join(format!("enhancer-log-{}.csv", process::id()));
+    let lines = results.iter().map(| result | result.name()).collect::<Vec::<&str>>().join("\n");
+    fs::write(&path, format!("result\n{lines}\n")).unwrap();
+    let sim = EnhancerSimulation::from_result_log(&path, &rates).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let max_level = rates.len() as i32 - 1;
+    let mut level = 0;
+    for &result in &results {
+      level = apply_result(level, result, 0, max_level, HalveRounding::Floor, rates[level as usize].reset_level);
+    }
+
+    assert_eq!(sim.level, level);
+    assert_eq!(sim.level, 1);
+    assert_eq!(sim.attempt_count, results.len() as i32);
+  }
+
+  #[test]
+  fn charges_cancel_the_first_downgrades() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let mut sim = EnhancerSimulation::create_at_seeded(&rates, 5, 1);
+    sim.set_rules(EnhanceRules { protection_charges: 5, ..EnhanceRules::default() });
+
+    // Middle of level 5's downgrade region
+    let cumulative = cumulative_distribution(&rates[5]);
+    let downgrade_roll = ((cumulative[2] + cumulative[3]) / 2.0) as f32;
+    let mut rolls = ScriptedRolls::new(vec![downgrade_roll; 6], ScriptEnd::Panic);
+
+    for _attempt in 0..5 {
+      sim.enhance_with(&mut rolls);
+      assert_eq!(sim.level, 5);
+    }
+    assert_eq!(sim.charges_used, 5);
+    assert_eq!(sim.charges_remaining, 0);
+
+    sim.enhance_with(&mut rolls);
+    assert_eq!(sim.level, 4);
+  }
+
+  #[test]
+  fn streaming_writes_one_line_per_attempt() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let mut simulation = EnhancerSimulation::create_seeded(&rates, 1);
+    let mut output = Vec::<u8>::new();
+
+    run_streaming_events(&mut simulation, &mut output).unwrap();
+
+    let lines = String::from_utf8(output).unwrap();
+    assert_eq!(lines.lines().count(), simulation.attempt_count as usize);
+    let last = serde_json::from_str::<serde_json::Value>(lines.lines().last().unwrap()).unwrap();
+    assert_eq!(last["attempt"], simulation.attempt_count);
+    assert_eq!(last["level"], simulation.level);
+  }
+
+  #[test]
+  fn starting_higher_skips_the_lower_levels() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let from_zero = seeded_mean_attempts(&default_params(), 500, 1);
+
+    let mut simulations = (0..500).map(| i | EnhancerSimulation::create_at_seeded(&rates, 6, actor_seed(1, i))).collect::<Vec::<_>>();
+    EnhancerSimulation::run_to_completion(&mut simulations, | _progress | {});
+
+    for simulation in simulations.iter() {
+      assert!(simulation.history[..=6].iter().all(| &attempts | attempts == 0));
+    }
+    assert!(mean_attempts(&simulations) < from_zero);
+  }
+
+  #[test]
+  fn reset_lands_on_the_highest_milestone_below() {
+    let mut params = default_params();
+    params.reset_milestones = vec![0, 5];
+    let rates = generate_rates(&params).unwrap();
+    let mut sim = EnhancerSimulation::create_at_seeded(&rates, 8, 1);
+
+    sim.enhance_with(&mut ScriptedRolls::new(vec![middle_of(&rates[8], EnhanceResult::Reset)], ScriptEnd::Panic));
+
+    assert_eq!(rates[8].reset_level, 5);
+    assert_eq!(rates[4].reset_level, 0);
+    assert_eq!(sim.level, 5);
+  }
+
+  #[test]
+  fn population_follows_the_start_distribution() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let population = EnhancerSimulation::create_population(&rates, &[(0, 0.5), (3, 0.3), (6, 0.2)], 101, 1).unwrap();
+
+    let count_at = | level: i32 | population.iter().filter(| sim | sim.level == level).count();
+    assert_eq!(population.len(), 101);
+    // 50.5, 30.3 and 20.2, the one leftover actor goes to the largest remainder
+    assert_eq!((count_at(0), count_at(3), count_at(6)), (51, 30, 20));
+    assert!(population.windows(2).all(| pair | pair[0].level <= pair[1].level));
+  }
+
+  #[test]
+  fn actors_pass_the_old_cap_only_once_it_is_raised() {
+    let mut params = default_params();
+    // Without losses everything after first reaching level 3 has to happen after the raise
+    params.max_downgrade_rate = 0.0;
+    let rates = generate_rates(&params).unwrap();
+    let mut simulations = EnhancerSimulation::create_many_seeded(&rates, 50, 1);
+
+    let iterations = EnhancerSimulation::run_with_schedule(&mut simulations, &[(0, 3), (1_000, 10)]);
+
+    assert!(simulations.iter().all(| sim | sim.is_maxed()));
+    // Every actor was waiting at 3 well before iteration 1000, so the run skipped straight to it and
+    // then took as long as the slowest actor's attempts from 3 onwards
+    let slowest_after_raise = simulations.iter().map(| sim | sim.attempt_count - sim.history[3]).max().unwrap();
+    assert!(simulations.iter().all(| sim | sim.history[3] < 1_000));
+    assert_eq!(iterations, 1_000 + slowest_after_raise);
+  }
+
+  #[test]
+  fn mercy_shortens_the_tail() {
+    let p99_attempts = | params: &EnhancerParams | {
+      let rates = generate_rates(params).unwrap();
+      let mut simulations = EnhancerSimulation::create_many_seeded(&rates, 1_000, 1);
+      EnhancerSimulation::set_rules_many(&mut simulations, EnhanceRules::from_params(params));
+      EnhancerSimulation::run_to_completion(&mut simulations, | _progress | {});
+
+      let mut attempts = simulations.iter().map(| sim | sim.attempt_count as f64).collect::<Vec::<f64>>();
+      attempts.sort_by(f64::total_cmp);
+      percentile(&attempts, 0.99)
+    };
+
+    let mut merciful = default_params();
+    merciful.mercy_per_fail = 0.02;
+
+    assert!(p99_attempts(&merciful) < 0.8 * p99_attempts(&default_params()));
+  }
+
+  // Roll value in the middle of `result`'s region of `rate`
+  fn middle_of(rate: &EnhanceRate, result: EnhanceResult) -> f32 {
+    let cumulative = cumulative_distribution(rate);
+    let i = ROLL_ORDER.iter().position(| &candidate | candidate == result).unwrap();
+    let lower = if i == 0 { 0.0 } else { cumulative[i - 1] };
+    assert!(cumulative[i] > lower, "{result:?} can't happen at level {}", rate.level);
+    return ((lower + cumulative[i]) / 2.0) as f32;
+  }
+
+  #[test]
+  fn scripted_rolls_give_an_exact_trajectory() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let steps = [
+      (EnhanceResult::Upgrade, 6),
+      (EnhanceResult::Upgrade, 7),
+      (EnhanceResult::Downgrade, 6),
+      (EnhanceResult::Reset, 0),
+      (EnhanceResult::Upgrade, 1)
+    ];
+
+    let mut level = 5;
+    let mut rolls = Vec::<f32>::new();
+    for &(result, next_level) in steps.iter() {
+      rolls.push(middle_of(&rates[level as usize], result));
+      level = next_level;
+    }
+
+    let mut sim = EnhancerSimulation::create_at_seeded(&rates, 5, 1);
+    let mut source = ScriptedRolls::new(rolls, ScriptEnd::Panic);
+    let mut levels = Vec::<i32>::new();
+    for _step in steps.iter() {
+      sim.enhance_with(&mut source);
+      levels.push(sim.level);
+    }
+
+    assert_eq!(levels, steps.iter().map(| &(_, level) | level).collect::<Vec::<i32>>());
+    assert_eq!(source.used(), steps.len());
+    assert_eq!(sim.attempt_count, steps.len() as i32);
+    // Only levels 6 and 7 were new, first reached on attempts 1 and 2
+    assert_eq!(sim.history[6..], [1, 2]);
+  }
+
+  #[test]
+  fn rolled_values_span_the_spread() {
+    let params = EnhancerParams { value_spread: 0.5, ..default_params() };
+    let rates = generate_rates(&params).unwrap();
+    let top = &rates[rates.len() - 1];
+    let simulations = completed_batch(&rates, 1_000, 1);
+
+    let values = simulations.iter().map(| sim | sim.current_value).collect::<Vec::<f32>>();
+    let low = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let high = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+    // 1000 uniform draws leave gaps of about a thousandth of the range at either end
+    let slack = (top.value_max - top.value_min) * 0.01;
+    assert!(low >= top.value_min && low < top.value_min + slack, "lowest value {low}");
+    assert!(high <= top.value_max && high > top.value_max - slack, "highest value {high}");
+  }
+
+  #[test]
+  fn bad_start_distributions_are_rejected() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let rejected = | distribution: &[(i32, f64)] | EnhancerSimulation::create_population(&rates, distribution, 10, 1).unwrap_err();
+
+    assert_eq!(rejected(&[(0, 1.5), (3, -0.5)]).level, Some(3));
+    assert_eq!(rejected(&[(0, 0.5), (3, 0.3)]).level, None);
+    assert_eq!(rejected(&[(0, f64::NAN), (3, 1.0)]).level, Some(0));
+    assert_eq!(rejected(&[(0, 0.5), (11, 0.5)]).level, Some(11));
+    assert_eq!(rejected(&[(-1, 1.0)]).field, "start_distribution");
+  }
+}
@@ -0,0 +1,735 @@
+use std::fmt;
+use serde::Serialize;
+use crate::params::EnhanceRules;
+use crate::rate::EnhanceRate;
+use crate::simulation::EnhancerSimulation;
+
+// Summary of the attempts actors took to first reach a level
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct LevelStats {
+  pub level: i32,
+  pub sample_count: usize,
+  pub mean: f64,
+  pub median: f64,
+  pub p25: f64,
+  pub p75: f64,
+  pub min: f64,
+  pub max: f64,
+  // Population variance of the samples
+  pub variance: f64,
+  pub std_dev: f64,
+  // std_dev / mean, how swingy the level is relative to its typical cost. 0 when the mean is 0
+  pub coefficient_of_variation: f64
+}
+
+impl LevelStats {
+  // Levels reached by fewer actors than this have noisy percentiles
+  pub const SPARSE_SAMPLE_COUNT: usize = 30;
+
+  pub fn from_samples(level: i32, samples: &Vec::<f64>) -> LevelStats {
+    let mut sorted = samples.clone();
+    sorted.sort_by(f64::total_cmp);
+
+    let sample_count = sorted.len();
+    let mean = sorted.iter().sum::<f64>() / sample_count as f64;
+    let median = percentile(&sorted, 0.5);
+    let p25 = percentile(&sorted, 0.25);
+    let p75 = percentile(&sorted, 0.75);
+    let min = sorted[0];
+    let max = sorted[sample_count - 1];
+
+    let variance = sorted.iter().map(| x | (x - mean) * (x - mean)).sum::<f64>() / sample_count as f64;
+    let std_dev = variance.sqrt();
+    let coefficient_of_variation = if mean == 0.0 { 0.0 } else { std_dev / mean };
+
+    return LevelStats {
+      level, sample_count, mean, median, p25, p75, min, max,
+      variance, std_dev, coefficient_of_variation
+    };
+  }
+
+  pub fn is_sparse(&self) -> bool {
+    return self.sample_count < Self::SPARSE_SAMPLE_COUNT;
+  }
+}
+
+// `level_statistics` as a GitHub flavoured Markdown table with right aligned numbers
+pub fn format_stats_markdown(stats: &Vec::<LevelStats>) -> String {
+  let mut output = String::from("| Level | Samples | Mean | Median | P25 | P75 | Min | Max | Std Dev |\n");
+  output.push_str("|---:|---:|---:|---:|---:|---:|---:|---:|---:|\n");
+
+  for level in stats {
+    output.push_str(&format!(
+      "| {} | {} | {:.1} | {:.1} | {:.1} | {:.1} | {:.1} | {:.1} | {:.1} |\n",
+      level.level, level.sample_count, level.mean, level.median, level.p25, level.p75, level.min, level.max, level.std_dev
+    ));
+  }
+
+  return output;
+}
+
+// Per level statistics of the attempts taken to first reach each level.
+// Only levels that at least one actor reached are included
+pub fn level_statistics(simulations: &Vec::<EnhancerSimulation>) -> Vec::<LevelStats> {
+  let data = EnhancerSimulation::boxplot_data(simulations);
+  return data.iter()
+    .enumerate()
+    .map(| (level, samples) | LevelStats::from_samples(level as i32, samples))
+    .collect();
+}
+
+// A `level_quantile` quantile outside [0, 1]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidQuantileError {
+  pub q: f64
+}
+
+impl fmt::Display for InvalidQuantileError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    return write!(f, "quantile must be within [0, 1], got {}", self.q);
+  }
+}
+
+impl std::error::Error for InvalidQuantileError {}
+
+// Linearly interpolated `q` quantile of the attempts taken to first reach `level`, None if no
+// actor reached it. Errors if `q` is outside [0, 1]
+pub fn level_quantile(simulations: &Vec::<EnhancerSimulation>, level: i32, q: f64) -> Result<Option::<f64>, InvalidQuantileError> {
+  // NaN fails the check too
+  if !(0.0..=1.0).contains(&q) {
+    return Err(InvalidQuantileError { q });
+  }
+
+  let mut samples = simulations.iter()
+    .filter_map(| sim | sim.history.get(usize::try_from(level).ok()?))
+    .map(| &reached_at | reached_at as f64)
+    .collect::<Vec::<f64>>();
+
+  if samples.is_empty() {
+    return Ok(None);
+  }
+
+  samples.sort_by(f64::total_cmp);
+  return Ok(Some(percentile(&samples, q)));
+}
+
+// Normal approximation 95% confidence interval for the mean attempts taken to first reach a level
+#[derive(Clone, Copy, Debug)]
+pub struct MeanInterval {
+  pub level: i32,
+  pub sample_count: usize,
+  pub mean: f64,
+  pub lower: f64,
+  pub upper: f64,
+  // False when too few actors reached the level for the normal approximation to hold
+  pub reliable: bool
+}
+
+impl MeanInterval {
+  const Z_95: f64 = 1.96;
+
+  // With a single sample the spread is unknown, so the interval is unbounded
+  pub fn from_samples(level: i32, samples: &Vec::<f64>) -> MeanInterval {
+    let sample_count = samples.len();
+    let n = sample_count as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+
+    let half_width = if sample_count < 2 {
+      f64::INFINITY
+    } else {
+      let variance = samples.iter().map(| x | (x - mean) * (x - mean)).sum::<f64>() / (n - 1.0);
+      Self::Z_95 * (variance / n).sqrt()
+    };
+
+    let lower = mean - half_width;
+    let upper = mean + half_width;
+    let reliable = sample_count >= LevelStats::SPARSE_SAMPLE_COUNT;
+
+    return MeanInterval { level, sample_count, mean, lower, upper, reliable };
+  }
+}
+
+// Companion to `level_statistics` with the uncertainty of each level's mean
+pub fn level_mean_intervals(simulations: &Vec::<EnhancerSimulation>) -> Vec::<MeanInterval> {
+  let data = EnhancerSimulation::boxplot_data(simulations);
+  return data.iter()
+    .enumerate()
+    .map(| (level, samples) | MeanInterval::from_samples(level as i32, samples))
+    .collect();
+}
+
+// Distribution of the total materials each actor consumed on its way to max level
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct MaterialStats {
+  pub mean: f64,
+  pub median: f64,
+  pub p95: f64
+}
+
+// Abandoned and destroyed actors are left out, see `completed_actors`
+pub fn material_statistics(simulations: &Vec::<EnhancerSimulation>) -> MaterialStats {
+  let mut used = completed_actors(simulations)
+    .map(| sim | sim.materials_used as f64)
+    .collect::<Vec::<f64>>();
+  used.sort_by(f64::total_cmp);
+
+  if used.is_empty() {
+    return MaterialStats { mean: f64::NAN, median: f64::NAN, p95: f64::NAN };
+  }
+
+  let mean = used.iter().sum::<f64>() / used.len() as f64;
+  let median = percentile(&used, 0.5);
+  let p95 = percentile(&used, 0.95);
+
+  return MaterialStats { mean, median, p95 };
+}
+
+// Distribution of the value actors ended up with at max level. The low end is the interesting part
+// with a value spread, those are the actors that maxed the item but rolled badly
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ValueStats {
+  pub mean: f64,
+  pub min: f64,
+  pub p5: f64,
+  pub median: f64,
+  pub max: f64
+}
+
+// Abandoned and destroyed actors are left out, see `completed_actors`
+pub fn value_statistics(simulations: &Vec::<EnhancerSimulation>) -> ValueStats {
+  let mut values = completed_actors(simulations)
+    .map(| sim | sim.current_value as f64)
+    .collect::<Vec::<f64>>();
+  values.sort_by(f64::total_cmp);
+
+  if values.is_empty() {
+    return ValueStats { mean: f64::NAN, min: f64::NAN, p5: f64::NAN, median: f64::NAN, max: f64::NAN };
+  }
+
+  let mean = values.iter().sum::<f64>() / values.len() as f64;
+  let min = values[0];
+  let p5 = percentile(&values, 0.05);
+  let median = percentile(&values, 0.5);
+  let max = values[values.len() - 1];
+
+  return ValueStats { mean, min, p5, median, max };
+}
+
+// Distribution of the wall-clock seconds each actor took to reach max level
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct TimeStats {
+  pub mean: f64,
+  pub median: f64,
+  pub p95: f64
+}
+
+// Abandoned and destroyed actors are left out, see `completed_actors`
+pub fn time_statistics(simulations: &Vec::<EnhancerSimulation>) -> TimeStats {
+  let mut seconds = completed_actors(simulations)
+    .map(| sim | sim.time_spent)
+    .collect::<Vec::<f64>>();
+  seconds.sort_by(f64::total_cmp);
+
+  if seconds.is_empty() {
+    return TimeStats { mean: f64::NAN, median: f64::NAN, p95: f64::NAN };
+  }
+
+  let mean = seconds.iter().sum::<f64>() / seconds.len() as f64;
+  let median = percentile(&seconds, 0.5);
+  let p95 = percentile(&seconds, 0.95);
+
+  return TimeStats { mean, median, p95 };
+}
+
+// Seconds as "Xh Ym", rounded to the nearest minute
+pub fn format_duration(seconds: f64) -> String {
+  if !seconds.is_finite() {
+    return String::from("n/a");
+  }
+
+  let minutes = (seconds / 60.0).round() as i64;
+  return format!("{}h {}m", minutes / 60, minutes % 60);
+}
+
+// Most common current level across the batch and the fraction of actors at it. Ties go to the
+// lower level. (0, 0.0) for an empty batch
+pub fn modal_level(simulations: &Vec::<EnhancerSimulation>) -> (i32, f64) {
+  let mut counts = Vec::<usize>::new();
+  for sim in simulations {
+    let level = sim.level as usize;
+    if level >= counts.len() {
+      counts.resize(level + 1, 0);
+    }
+    counts[level] += 1;
+  }
+
+  let mut modal = 0;
+  for level in 0..counts.len() {
+    if counts[level] > counts[modal] {
+      modal = level;
+    }
+  }
+
+  let count = counts.get(modal).copied().unwrap_or(0);
+  let fraction = if simulations.is_empty() { 0.0 } else { count as f64 / simulations.len() as f64 };
+  return (modal as i32, fraction);
+}
+
+// Where an actor with a hard budget of `attempts` attempts most likely ends up, see `modal_level`
+pub fn modal_level_at_budget(rates: &Vec::<EnhanceRate>, attempts: i32, actors: i32, seed: u64, rules: EnhanceRules) -> (i32, f64) {
+  let mut simulations = EnhancerSimulation::create_many_seeded(rates, actors, seed);
+  EnhancerSimulation::set_rules_many(&mut simulations, rules);
+  EnhancerSimulation::run_for_attempts(&mut simulations, attempts);
+
+  return modal_level(&simulations);
+}
+
+// Fraction of actors that first reached `level` within `attempts` attempts. Actors that never
+// reached the level count as failures
+pub fn success_probability_within(simulations: &Vec::<EnhancerSimulation>, level: i32, attempts: i32) -> f64 {
+  if level < 0 || simulations.is_empty() {
+    return 0.0;
+  }
+
+  let successes = simulations.iter()
+    .filter(| sim | sim.history.get(level as usize).is_some_and(| &reached_at | reached_at <= attempts))
+    .count();
+
+  return successes as f64 / simulations.len() as f64;
+}
+
+// Whether a curve satisfies a design requirement like "95% of actors max within 1000 attempts".
+// `shortfall` is how far the achieved fraction falls short of the target, 0 when it passes
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct BudgetVerdict {
+  pub passed: bool,
+  pub achieved_fraction: f64,
+  pub shortfall: f64
+}
+
+// Runs `actors` actors seeded with `seed` for at most `budget` attempts each and checks that at
+// least `target_fraction` of them reach max level. Destroyed actors count as not reaching it
+pub fn meets_budget(rates: &Vec::<EnhanceRate>, budget: i32, target_fraction: f64, actors: i32, seed: u64, rules: EnhanceRules) -> BudgetVerdict {
+  let mut simulations = EnhancerSimulation::create_many_seeded(rates, actors, seed);
+  EnhancerSimulation::set_rules_many(&mut simulations, rules);
+  EnhancerSimulation::run_for_attempts(&mut simulations, budget);
+
+  let maxed = simulations.iter().filter(| sim | sim.is_maxed()).count();
+  let achieved_fraction = if simulations.is_empty() { 0.0 } else { maxed as f64 / simulations.len() as f64 };
+
+  return BudgetVerdict {
+    passed: achieved_fraction >= target_fraction,
+    achieved_fraction,
+    shortfall: f64::max(0.0, target_fraction - achieved_fraction)
+  };
+}
+
+// How many actors ended early, either giving up under the attempt cap or being destroyed, and what
+// fraction of the batch that is
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct AbandonStats {
+  pub count: usize,
+  pub fraction: f64
+}
+
+impl AbandonStats {
+  fn count_where(simulations: &Vec::<EnhancerSimulation>, predicate: impl Fn(&EnhancerSimulation) -> bool) -> AbandonStats {
+    let count = simulations.iter().filter(| sim | predicate(sim)).count();
+    let fraction = if simulations.is_empty() { 0.0 } else { count as f64 / simulations.len() as f64 };
+    return AbandonStats { count, fraction };
+  }
+}
+
+pub fn abandon_statistics(simulations: &Vec::<EnhancerSimulation>) -> AbandonStats {
+  return AbandonStats::count_where(simulations, | sim | sim.abandoned);
+}
+
+pub fn destroy_statistics(simulations: &Vec::<EnhancerSimulation>) -> AbandonStats {
+  return AbandonStats::count_where(simulations, | sim | sim.destroyed);
+}
+
+// How much of their protection charges actors went through
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ChargeStats {
+  pub mean_used: f64,
+  // Fraction of actors that ran out of charges at some point
+  pub exhausted_fraction: f64
+}
+
+pub fn charge_statistics(simulations: &Vec::<EnhancerSimulation>) -> ChargeStats {
+  if simulations.is_empty() {
+    return ChargeStats { mean_used: f64::NAN, exhausted_fraction: 0.0 };
+  }
+
+  let count = simulations.len() as f64;
+  let mean_used = simulations.iter().map(| sim | sim.charges_used as f64).sum::<f64>() / count;
+  let exhausted = simulations.iter().filter(| sim | sim.charges_used > 0 && sim.charges_remaining == 0).count();
+
+  return ChargeStats { mean_used, exhausted_fraction: exhausted as f64 / count };
+}
+
+// Currency spent across the batch. Unlike the other totals this includes destroyed actors, their
+// spend is real and refunds only make sense for them. Abandoned actors are still left out
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct CostStats {
+  pub mean_spent: f64,
+  pub mean_refunded: f64,
+  // Spent minus refunded, per actor
+  pub net_mean: f64,
+  pub net_median: f64,
+  pub net_p95: f64
+}
+
+pub fn cost_statistics(simulations: &Vec::<EnhancerSimulation>) -> CostStats {
+  let counted = simulations.iter()
+    .filter(| sim | !sim.abandoned)
+    .collect::<Vec::<&EnhancerSimulation>>();
+
+  if counted.is_empty() {
+    return CostStats { mean_spent: f64::NAN, mean_refunded: f64::NAN, net_mean: f64::NAN, net_median: f64::NAN, net_p95: f64::NAN };
+  }
+
+  let count = counted.len() as f64;
+  let mean_spent = counted.iter().map(| sim | sim.total_cost).sum::<f64>() / count;
+  let mean_refunded = counted.iter().map(| sim | sim.refunded).sum::<f64>() / count;
+
+  let mut net = counted.iter().map(| sim | sim.net_cost()).collect::<Vec::<f64>>();
+  net.sort_by(f64::total_cmp);
+
+  let net_mean = net.iter().sum::<f64>() / count;
+  let net_median = percentile(&net, 0.5);
+  let net_p95 = percentile(&net, 0.95);
+
+  return CostStats { mean_spent, mean_refunded, net_mean, net_median, net_p95 };
+}
+
+// Actors that actually reached max level. The statistics over final totals only look at these,
+// an abandoned actor's totals are cut off by the attempt cap, a destroyed actor's by losing the
+// item and a still running actor's by not being done yet, any of them would distort the tail
+pub fn completed_actors<'s, 'a>(simulations: &'s Vec::<EnhancerSimulation<'a>>) -> impl Iterator<Item = &'s EnhancerSimulation<'a>> {
+  return simulations.iter().filter(| sim | sim.is_maxed());
+}
+
+// Mean attempts to max over the actors that have completed so far, fed one actor at a time as they
+// reach max during a run
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunningStats {
+  count: usize,
+  total: f64
+}
+
+impl RunningStats {
+  pub fn add(&mut self, attempts: f64) {
+    self.count += 1;
+    self.total += attempts;
+  }
+
+  // NaN until the first actor completes
+  pub fn current_mean(&self) -> f64 {
+    return self.total / self.count as f64;
+  }
+
+  pub fn sample_count(&self) -> usize {
+    return self.count;
+  }
+}
+
+// Mean final attempt count of the actors that reached max level, NaN if none did
+pub fn mean_attempts(simulations: &Vec::<EnhancerSimulation>) -> f64 {
+  let mut total = 0.0;
+  let mut count = 0;
+  for sim in completed_actors(simulations) {
+    total += sim.attempt_count as f64;
+    count += 1;
+  }
+
+  return total / count as f64;
+}
+
+// Gini coefficient of the final attempt counts of the completed actors, from 0 when every actor
+// took the same attempts up to nearly 1 when a few actors took almost all of them. A fairness
+// number to put next to the mean, a high value means luck decides how long the grind is. 0 for
+// fewer than two actors or if nobody made an attempt
+pub fn attempt_gini(simulations: &Vec::<EnhancerSimulation>) -> f64 {
+  let mut attempts = completed_actors(simulations)
+    .map(| sim | sim.attempt_count as f64)
+    .collect::<Vec::<f64>>();
+  attempts.sort_by(f64::total_cmp);
+
+  let n = attempts.len() as f64;
+  let total = attempts.iter().sum::<f64>();
+  if attempts.len() < 2 || total <= 0.0 {
+    return 0.0;
+  }
+
+  // G = 2 * sum(i * x_i) / (n * sum(x)) - (n + 1) / n, with i counting from 1 over ascending x
+  let weighted = attempts.iter().enumerate().map(| (i, x) | (i + 1) as f64 * x).sum::<f64>();
+  return 2.0 * weighted / (n * total) - (n + 1.0) / n;
+}
+
+// Linearly interpolated percentile of already sorted data, `fraction` is in [0, 1]. NaN if there is
+// no data
+pub fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+  if sorted.is_empty() {
+    return f64::NAN;
+  }
+
+  let position = fraction * (sorted.len() - 1) as f64;
+  let lower = position.floor() as usize;
+  let upper = position.ceil() as usize;
+  let weight = position - lower as f64;
+
+  return sorted[lower] + (sorted[upper] - sorted[lower]) * weight;
+}
+
+// Empirical CDF of the final attempt counts as (attempts, fraction of actors done within attempts).
+// Actors sharing an attempt count collapse into a single point, so x values are strictly increasing
+// and the last point is always exactly 1.0. Only completed actors are counted
+pub fn attempts_cdf(simulations: &Vec::<EnhancerSimulation>) -> Vec::<(f64, f64)> {
+  return empirical_cdf(completed_actors(simulations).map(| sim | sim.attempt_count as f64).collect());
+}
+
+// `attempts_cdf` for the currency each completed actor spent, before refunds
+pub fn cost_cdf(simulations: &Vec::<EnhancerSimulation>) -> Vec::<(f64, f64)> {
+  return empirical_cdf(completed_actors(simulations).map(| sim | sim.total_cost).collect());
+}
+
+fn empirical_cdf(mut values: Vec::<f64>) -> Vec::<(f64, f64)> {
+  values.sort_by(f64::total_cmp);
+
+  let total = values.len() as f64;
+  let mut output = Vec::<(f64, f64)>::new();
+
+  for i in 0..values.len() {
+    let is_last_of_value = i + 1 == values.len() || values[i + 1] != values[i];
+    if is_last_of_value {
+      output.push((values[i], (i + 1) as f64 / total));
+    }
+  }
+
+  return output;
+}
+
+// One bar of `attempts_histogram`, counting the actors whose attempts fell within [lower, upper).
+// The last bin also includes its upper edge
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct HistogramBin {
+  pub lower: f64,
+  pub upper: f64,
+  pub count: usize
+}
+
+// Final attempt counts of the completed actors split into `bins` equally wide bins spanning the
+// smallest to the largest count. Empty if no actor completed
+pub fn attempts_histogram(simulations: &Vec::<EnhancerSimulation>, bins: usize) -> Vec::<HistogramBin> {
+  let attempts = completed_actors(simulations)
+    .map(| sim | sim.attempt_count as f64)
+    .collect::<Vec::<f64>>();
+
+  if attempts.is_empty() || bins == 0 {
+    return Vec::new();
+  }
+
+  let min = attempts.iter().fold(f64::INFINITY, | min, &x | f64::min(min, x));
+  let max = attempts.iter().fold(f64::NEG_INFINITY, | max, &x | f64::max(max, x));
+  // Every actor taking the same attempts still gets a bin of non-zero width
+  let width = f64::max(1.0, max - min) / bins as f64;
+
+  let mut output = (0..bins)
+    .map(| i | HistogramBin { lower: min + width * i as f64, upper: min + width * (i + 1) as f64, count: 0 })
+    .collect::<Vec::<HistogramBin>>();
+
+  for x in attempts {
+    let bin = usize::min(bins - 1, ((x - min) / width) as usize);
+    output[bin].count += 1;
+  }
+
+  return output;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::params::{ default_params, generate_rates };
+  use crate::testing::completed_batch;
+
+  #[test]
+  fn attempts_cdf_is_monotonic_and_ends_at_one() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let simulations = completed_batch(&rates, 500, 1);
+    let cdf = attempts_cdf(&simulations);
+
+    for pair in cdf.windows(2) {
+      assert!(pair[0].0 < pair[1].0);
+      assert!(pair[0].1 <= pair[1].1);
+    }
+    assert_eq!(cdf.last().unwrap().1, 1.0);
+  }
+
+  #[test]
+  fn mean_interval_widens_as_samples_shrink() {
+    let pattern = (1..=10).map(| x | x as f64).collect::<Vec::<f64>>();
+    let width = | repeats: usize | {
+      let samples = pattern.repeat(repeats);
+      let interval = MeanInterval::from_samples(1, &samples);
+      interval.upper - interval.lower
+    };
+
+    assert!(width(1) > width(10));
+    assert!(width(10) > width(100));
+    assert!(MeanInterval::from_samples(1, &vec![5.0]).upper.is_infinite());
+    assert!(!MeanInterval::from_samples(1, &pattern).reliable);
+  }
+
+  #[test]
+  fn success_probability_grows_with_attempts_and_falls_with_level() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let simulations = completed_batch(&rates, 500, 2);
+
+    for level in 0..rates.len() as i32 {
+      let mut previous = 0.0;
+      for attempts in (0..3000).step_by(50) {
+        let probability = success_probability_within(&simulations, level, attempts);
+        assert!(probability >= previous);
+        previous = probability;
+      }
+    }
+
+    for attempts in [10, 100, 1000] {
+      for level in 1..rates.len() as i32 {
+        assert!(success_probability_within(&simulations, level, attempts) <= success_probability_within(&simulations, level - 1, attempts));
+      }
+    }
+  }
+
+  #[test]
+  fn variance_is_non_negative_and_zero_for_constant_samples() {
+    let rates = generate_rates(&default_params()).unwrap();
+    for stats in level_statistics(&completed_batch(&rates, 300, 3)) {
+      assert!(stats.variance >= 0.0);
+      assert!(stats.coefficient_of_variation >= 0.0);
+    }
+
+    let constant = LevelStats::from_samples(4, &vec![12.0; 50]);
+    assert_eq!(constant.variance, 0.0);
+    assert_eq!(constant.std_dev, 0.0);
+    assert_eq!(constant.coefficient_of_variation, 0.0);
+  }
+
+  #[test]
+  fn stats_markdown_rows_have_every_column() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let markdown = format_stats_markdown(&level_statistics(&completed_batch(&rates, 100, 4)));
+    let lines = markdown.lines().collect::<Vec::<&str>>();
+
+    assert_eq!(lines.len(), 2 + rates.len());
+    assert_eq!(lines[1], "|---:|---:|---:|---:|---:|---:|---:|---:|---:|");
+    for line in lines {
+      assert_eq!(line.matches('|').count(), 10, "{line}");
+    }
+  }
+
+  #[test]
+  fn modal_level_rises_with_the_budget() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let (small, _) = modal_level_at_budget(&rates, 5, 500, 1, EnhanceRules::default());
+    let (large, large_fraction) = modal_level_at_budget(&rates, 3000, 500, 1, EnhanceRules::default());
+
+    assert!(small <= 3, "modal level after 5 attempts is {small}");
+    assert!(large > small);
+    assert!(large_fraction > 0.0 && large_fraction <= 1.0);
+  }
+
+  #[test]
+  fn median_quantile_matches_level_statistics() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let simulations = completed_batch(&rates, 301, 5);
+
+    for stats in level_statistics(&simulations) {
+      assert_eq!(level_quantile(&simulations, stats.level, 0.5), Ok(Some(stats.median)));
+      assert_eq!(level_quantile(&simulations, stats.level, 0.0), Ok(Some(stats.min)));
+      assert_eq!(level_quantile(&simulations, stats.level, 1.0), Ok(Some(stats.max)));
+    }
+    assert_eq!(level_quantile(&simulations, 11, 0.5), Ok(None));
+  }
+
+  #[test]
+  fn running_mean_ends_at_the_batch_mean() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let mut simulations = EnhancerSimulation::create_many_seeded(&rates, 200, 1);
+    let mut last = None;
+
+    EnhancerSimulation::run_capped(&mut simulations, 100_000, | progress | last = Some(progress)).unwrap();
+
+    let last = last.unwrap();
+    assert_eq!(last.completed_count, 200);
+    assert!((last.running_mean - mean_attempts(&simulations)).abs() < 1e-9);
+    assert!(RunningStats::default().current_mean().is_nan());
+  }
+
+  #[test]
+  fn cost_cdf_is_monotonic_and_ends_at_one() {
+    let mut params = default_params();
+    params.cost_per_attempt = (0..params.max_level).map(| level | 1.0 + level as f32).collect();
+    let rates = generate_rates(&params).unwrap();
+    let simulations = completed_batch(&rates, 200, 1);
+
+    let cdf = cost_cdf(&simulations);
+    assert!(cdf.windows(2).all(| pair | pair[0].0 < pair[1].0 && pair[0].1 < pair[1].1));
+    assert_eq!(cdf.last().unwrap().1, 1.0);
+
+    let cheapest = simulations.iter().map(| sim | sim.total_cost).fold(f64::INFINITY, f64::min);
+    assert_eq!(cdf[0].0, cheapest);
+  }
+
+  #[test]
+  fn gini_is_zero_for_a_sure_curve_and_high_for_a_swingy_one() {
+    let mut sure = default_params();
+    sure.upgrade_rate_curve = 1.0;
+    sure.max_downgrade_rate = 0.0;
+    let sure_rates = generate_rates(&sure).unwrap();
+    assert_eq!(attempt_gini(&completed_batch(&sure_rates, 100, 1)), 0.0);
+
+    let mut swingy = default_params();
+    swingy.max_downgrade_rate = 0.3;
+    swingy.reset_ratio = 0.5;
+    swingy.min_reset_level = 1;
+    let swingy_rates = generate_rates(&swingy).unwrap();
+    let gini = attempt_gini(&completed_batch(&swingy_rates, 500, 1));
+    assert!(gini > 0.3, "gini {gini}");
+    assert!(gini < 1.0);
+  }
+
+  #[test]
+  fn budget_verdict_passes_easy_curves_and_fails_hard_ones() {
+    let mut easy = default_params();
+    easy.upgrade_rate_curve = 0.9;
+    easy.max_downgrade_rate = 0.1;
+    let easy_rates = generate_rates(&easy).unwrap();
+    let verdict = meets_budget(&easy_rates, 200, 0.9, 200, 1, EnhanceRules::from_params(&easy));
+    assert!(verdict.passed);
+    assert!(verdict.achieved_fraction >= 0.9);
+    assert_eq!(verdict.shortfall, 0.0);
+
+    let hard = default_params();
+    let hard_rates = generate_rates(&hard).unwrap();
+    let verdict = meets_budget(&hard_rates, 200, 0.9, 200, 1, EnhanceRules::from_params(&hard));
+    assert!(!verdict.passed);
+    assert!((verdict.shortfall - (0.9 - verdict.achieved_fraction)).abs() < 1e-12);
+
+    // Protection keeps actors from sliding back, so the same hard curve does better with it
+    let protected = EnhanceRules { protected_level: 5, ..EnhanceRules::from_params(&hard) };
+    assert!(meets_budget(&hard_rates, 200, 0.9, 200, 1, protected).achieved_fraction > verdict.achieved_fraction);
+  }
+
+  #[test]
+  fn quantiles_outside_the_unit_interval_are_rejected() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let simulations = completed_batch(&rates, 20, 1);
+
+    for q in [-0.1, 1.5, f64::NAN] {
+      assert!(level_quantile(&simulations, 3, q).is_err(), "{q} was accepted");
+    }
+    assert_eq!(level_quantile(&simulations, 3, 2.0), Err(InvalidQuantileError { q: 2.0 }));
+  }
+}
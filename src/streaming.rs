@@ -0,0 +1,178 @@
+// Aggregate statistics for batches too large to keep every actor around. Each actor is simulated,
+// folded into running totals and dropped before the next one starts
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use crate::params::EnhanceRules;
+use crate::rate::EnhanceRate;
+use crate::simulation::{ actor_seed, EnhancerSimulation };
+use crate::stats::percentile;
+
+// Final attempt counts kept for percentiles. Up to this many completed actors the percentiles are
+// exact, past it they come from a uniform sample of this size
+pub const RESERVOIR_SIZE: usize = 10_000;
+
+// What `StreamingAggregator::finish` produces. Per level figures are over the actors that reached
+// the level, like `level_statistics`, and the attempts to max only over completed actors
+#[derive(Clone, Debug)]
+pub struct AggregateStats {
+  pub actors: usize,
+  pub completed: usize,
+  pub abandoned: usize,
+  pub destroyed: usize,
+  pub level_sample_count: Vec::<usize>,
+  pub level_mean: Vec::<f64>,
+  // Population variance, as in `LevelStats`
+  pub level_variance: Vec::<f64>,
+  pub mean_attempts: f64,
+  pub median_attempts: f64,
+  pub p95_attempts: f64
+}
+
+pub struct StreamingAggregator {
+  actors: usize,
+  completed: usize,
+  abandoned: usize,
+  destroyed: usize,
+  level_count: Vec::<usize>,
+  level_sum: Vec::<f64>,
+  level_sum_squares: Vec::<f64>,
+  attempts_sum: f64,
+  reservoir: Vec::<f64>,
+  rng: ChaCha8Rng
+}
+
+impl StreamingAggregator {
+  // `seed` only drives which attempt counts the reservoir keeps
+  pub fn new(seed: u64) -> StreamingAggregator {
+    return StreamingAggregator {
+      actors: 0,
+      completed: 0,
+      abandoned: 0,
+      destroyed: 0,
+      level_count: Vec::new(),
+      level_sum: Vec::new(),
+      level_sum_squares: Vec::new(),
+      attempts_sum: 0.0,
+      reservoir: Vec::with_capacity(RESERVOIR_SIZE),
+      rng: ChaCha8Rng::seed_from_u64(seed)
+    };
+  }
+
+  pub fn add(&mut self, sim: &EnhancerSimulation) {
+    self.actors += 1;
+
+    for (level, &reached_at) in sim.history.iter().enumerate() {
+      if level == self.level_count.len() {
+        self.level_count.push(0);
+        self.level_sum.push(0.0);
+        self.level_sum_squares.push(0.0);
+      }
+
+      let attempts = reached_at as f64;
+      self.level_count[level] += 1;
+      self.level_sum[level] += attempts;
+      self.level_sum_squares[level] += attempts * attempts;
+    }
+
+    if sim.abandoned {
+      self.abandoned += 1;
+      return;
+    }
+
+    if sim.destroyed {
+      self.destroyed += 1;
+      return;
+    }
+
+    let attempts = sim.attempt_count as f64;
+    self.completed += 1;
+    self.attempts_sum += attempts;
+
+    // Reservoir sampling, every completed actor so far is equally likely to be in the sample
+    if self.reservoir.len() < RESERVOIR_SIZE {
+      self.reservoir.push(attempts);
+    } else {
+      let slot = self.rng.gen_range(0..self.completed);
+      if slot < RESERVOIR_SIZE {
+        self.reservoir[slot] = attempts;
+      }
+    }
+  }
+
+  pub fn finish(mut self) -> AggregateStats {
+    let mut level_mean = Vec::<f64>::with_capacity(self.level_count.len());
+    let mut level_variance = Vec::<f64>::with_capacity(self.level_count.len());
+    for level in 0..self.level_count.len() {
+      let n = self.level_count[level] as f64;
+      let mean = self.level_sum[level] / n;
+      level_mean.push(mean);
+      level_variance.push(f64::max(0.0, self.level_sum_squares[level] / n - mean * mean));
+    }
+
+    self.reservoir.sort_by(f64::total_cmp);
+    let (median_attempts, p95_attempts) = if self.reservoir.is_empty() {
+      (f64::NAN, f64::NAN)
+    } else {
+      (percentile(&self.reservoir, 0.5), percentile(&self.reservoir, 0.95))
+    };
+
+    return AggregateStats {
+      actors: self.actors,
+      completed: self.completed,
+      abandoned: self.abandoned,
+      destroyed: self.destroyed,
+      level_sample_count: self.level_count,
+      level_mean,
+      level_variance,
+      mean_attempts: self.attempts_sum / self.completed as f64,
+      median_attempts,
+      p95_attempts
+    };
+  }
+}
+
+// Simulates `actors` actors one at a time, so only one history is ever in memory. Actor i is seeded
+// the same way as in `create_many_seeded`, so the result describes the same batch a full run with
+// `seed` would produce
+pub fn run_streaming(rates: &Vec::<EnhanceRate>, actors: i32, seed: u64, rules: EnhanceRules) -> AggregateStats {
+  let mut aggregator = StreamingAggregator::new(seed);
+
+  for i in 0..actors {
+    let mut sim = EnhancerSimulation::create_seeded(rates, actor_seed(seed, i as u64));
+    sim.set_rules(rules);
+    while !sim.enhance() {}
+
+    aggregator.add(&sim);
+  }
+
+  return aggregator.finish();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::params::{ default_params, generate_rates };
+  use crate::stats::{ level_statistics, mean_attempts };
+  use crate::testing::completed_batch;
+
+  #[test]
+  fn streaming_matches_full_retention() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let streamed = run_streaming(&rates, 300, 5, EnhanceRules::default());
+
+    let simulations = completed_batch(&rates, 300, 5);
+    let retained = level_statistics(&simulations);
+
+    assert_eq!(streamed.actors, 300);
+    assert_eq!(streamed.completed, 300);
+    assert!((streamed.mean_attempts - mean_attempts(&simulations)).abs() < 1e-9);
+    assert_eq!(streamed.level_mean.len(), retained.len());
+    for (i, stats) in retained.iter().enumerate() {
+      assert_eq!(streamed.level_sample_count[i], stats.sample_count);
+      assert!((streamed.level_mean[i] - stats.mean).abs() < 1e-9);
+      assert!((streamed.level_variance[i] - stats.variance).abs() <= 1e-6 * stats.variance.max(1.0));
+    }
+    // Under `RESERVOIR_SIZE` actors the reservoir holds every count, so the median is exact
+    assert_eq!(streamed.median_attempts, retained.last().unwrap().median);
+  }
+}
@@ -0,0 +1,246 @@
+use crate::analysis::expected_attempts;
+use crate::params::{ generate_rates, EnhanceRules, EnhancerParams };
+use crate::rate::rates_reachable;
+use crate::simulation::EnhancerSimulation;
+use crate::stats::{ abandon_statistics, completed_actors, destroy_statistics, mean_attempts, percentile };
+
+// Relative error on the mean attempts at which `solve_for_target` accepts a value
+pub const SOLVE_TOLERANCE: f64 = 1e-4;
+const SOLVE_MAX_STEPS: i32 = 100;
+
+// Every sweep point is simulated from this seed, so differences between points come from the
+// parameter and not from the random rolls
+pub const SWEEP_SEED: u64 = 0x5EED;
+
+// The `f32` fields of `EnhancerParams` that can be swept
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamField {
+  ValueIncrement,
+  MinValue,
+  UpgradeRateCurve,
+  MaxUpgradeRate,
+  MinUpgradeRate,
+  UpgradeBoost,
+  DowngradeRateCurve,
+  MaxDowngradeRate,
+  HalveRatio,
+  ResetRatio,
+  DestroyRatio,
+  DestroyRefundRatio
+}
+
+impl ParamField {
+  pub fn name(&self) -> &'static str {
+    match self {
+      ParamField::ValueIncrement => "value_increment",
+      ParamField::MinValue => "min_value",
+      ParamField::UpgradeRateCurve => "upgrade_rate_curve",
+      ParamField::MaxUpgradeRate => "max_upgrade_rate",
+      ParamField::MinUpgradeRate => "min_upgrade_rate",
+      ParamField::UpgradeBoost => "upgrade_boost",
+      ParamField::DowngradeRateCurve => "downgrade_rate_curve",
+      ParamField::MaxDowngradeRate => "max_downgrade_rate",
+      ParamField::HalveRatio => "halve_ratio",
+      ParamField::ResetRatio => "reset_ratio",
+      ParamField::DestroyRatio => "destroy_ratio",
+      ParamField::DestroyRefundRatio => "destroy_refund_ratio"
+    }
+  }
+
+  pub fn get(&self, params: &EnhancerParams) -> f32 {
+    match self {
+      ParamField::ValueIncrement => params.value_increment,
+      ParamField::MinValue => params.min_value,
+      ParamField::UpgradeRateCurve => params.upgrade_rate_curve,
+      ParamField::MaxUpgradeRate => params.max_upgrade_rate,
+      ParamField::MinUpgradeRate => params.min_upgrade_rate,
+      ParamField::UpgradeBoost => params.upgrade_boost,
+      ParamField::DowngradeRateCurve => params.downgrade_rate_curve,
+      ParamField::MaxDowngradeRate => params.max_downgrade_rate,
+      ParamField::HalveRatio => params.halve_ratio,
+      ParamField::ResetRatio => params.reset_ratio,
+      ParamField::DestroyRatio => params.destroy_ratio,
+      ParamField::DestroyRefundRatio => params.destroy_refund_ratio
+    }
+  }
+
+  pub fn set(&self, params: &mut EnhancerParams, value: f32) {
+    match self {
+      ParamField::ValueIncrement => params.value_increment = value,
+      ParamField::MinValue => params.min_value = value,
+      ParamField::UpgradeRateCurve => params.upgrade_rate_curve = value,
+      ParamField::MaxUpgradeRate => params.max_upgrade_rate = value,
+      ParamField::MinUpgradeRate => params.min_upgrade_rate = value,
+      ParamField::UpgradeBoost => params.upgrade_boost = value,
+      ParamField::DowngradeRateCurve => params.downgrade_rate_curve = value,
+      ParamField::MaxDowngradeRate => params.max_downgrade_rate = value,
+      ParamField::HalveRatio => params.halve_ratio = value,
+      ParamField::ResetRatio => params.reset_ratio = value,
+      ParamField::DestroyRatio => params.destroy_ratio = value,
+      ParamField::DestroyRefundRatio => params.destroy_refund_ratio = value
+    }
+  }
+
+  // Copy of `params` with this field replaced by `value`
+  pub fn with(&self, params: &EnhancerParams, value: f32) -> EnhancerParams {
+    let mut output = params.clone();
+    self.set(&mut output, value);
+    return output;
+  }
+}
+
+// Simulates a full batch of `actors` for each value of `field` and returns (value, mean attempts
+// to max). A value that makes max level unreachable, or is invalid as a param, yields a mean of
+// infinity instead of hanging
+pub fn sweep(base: &EnhancerParams, field: ParamField, values: &[f32], actors: i32) -> Vec::<(f32, f64)> {
+  let mut output = Vec::<(f32, f64)>::with_capacity(values.len());
+
+  for &value in values {
+    let params = field.with(base, value);
+    let rates = match generate_rates(&params) {
+      Ok(rates) if rates_reachable(&rates).is_ok() => rates,
+      _ => {
+        output.push((value, f64::INFINITY));
+        continue;
+      }
+    };
+
+    let mut simulations = EnhancerSimulation::create_many_seeded(&rates, actors, SWEEP_SEED);
+    EnhancerSimulation::set_rules_many(&mut simulations, EnhanceRules::from_params(&params));
+    EnhancerSimulation::run_to_completion(&mut simulations, | _progress | {});
+
+    output.push((value, mean_attempts(&simulations)));
+  }
+
+  return output;
+}
+
+// Summary of one labelled parameter set from `run_batch`
+#[derive(Clone, Debug)]
+pub struct BatchResult {
+  pub label: String,
+  // Over completed actors, infinity if the set is invalid or makes max level unreachable without an
+  // attempt cap
+  pub mean_attempts: f64,
+  pub median_attempts: f64,
+  pub destroy_fraction: f64,
+  pub abandon_fraction: f64
+}
+
+// Simulates `actors` actors for every parameter set, all from the same `seed`, so actor i of one set
+// rolls the same numbers as actor i of another and the sets are compared fairly
+pub fn run_batch(param_sets: &[(String, EnhancerParams)], actors: i32, seed: u64) -> Vec::<BatchResult> {
+  let mut output = Vec::<BatchResult>::with_capacity(param_sets.len());
+
+  for (label, params) in param_sets {
+    let label = label.clone();
+    let rates = generate_rates(params).unwrap_or_default();
+
+    if rates.is_empty() || (rates_reachable(&rates).is_err() && params.attempt_cap.is_none()) {
+      output.push(BatchResult { label, mean_attempts: f64::INFINITY, median_attempts: f64::INFINITY, destroy_fraction: 0.0, abandon_fraction: 0.0 });
+      continue;
+    }
+
+    let mut simulations = EnhancerSimulation::create_many_seeded(&rates, actors, seed);
+    EnhancerSimulation::set_rules_many(&mut simulations, EnhanceRules::from_params(params));
+    EnhancerSimulation::run_to_completion(&mut simulations, | _progress | {});
+
+    let mut attempts = completed_actors(&simulations)
+      .map(| sim | sim.attempt_count as f64)
+      .collect::<Vec::<f64>>();
+    attempts.sort_by(f64::total_cmp);
+    let median_attempts = if attempts.is_empty() { f64::NAN } else { percentile(&attempts, 0.5) };
+
+    output.push(BatchResult {
+      label,
+      mean_attempts: mean_attempts(&simulations),
+      median_attempts,
+      destroy_fraction: destroy_statistics(&simulations).fraction,
+      abandon_fraction: abandon_statistics(&simulations).fraction
+    });
+  }
+
+  return output;
+}
+
+// Expected attempts to max with `field` set to `value`, computed analytically so the search is
+// deterministic. Attempt caps and protection are ignored, as in `expected_attempts`. Invalid values
+// are treated like unreachable ones
+fn expected_attempts_with(base: &EnhancerParams, field: ParamField, value: f32) -> f64 {
+  return match generate_rates(&field.with(base, value)) {
+    Ok(rates) => expected_attempts(&rates),
+    Err(_) => f64::INFINITY
+  };
+}
+
+// Bisects `field` within `bounds` for the value whose expected attempts to max is `target_mean`.
+// The mean has to be monotonic in the field over the bounds, which holds for every rate and ratio
+// field. None if the target lies outside what the bounds can produce
+pub fn solve_for_target(base: &EnhancerParams, field: ParamField, target_mean: f64, bounds: (f32, f32)) -> Option::<f32> {
+  let (mut low, mut high) = bounds;
+  let mut low_error = expected_attempts_with(base, field, low) - target_mean;
+  let high_error = expected_attempts_with(base, field, high) - target_mean;
+
+  // An unreachable bound is infinitely far above any target
+  let low_error_sign = low_error > 0.0;
+  let high_error_sign = high_error > 0.0;
+  if low_error_sign == high_error_sign {
+    let at_bound = if low_error.abs() < high_error.abs() { (low, low_error) } else { (high, high_error) };
+    return if at_bound.1.abs() <= target_mean * SOLVE_TOLERANCE { Some(at_bound.0) } else { None };
+  }
+
+  for _step in 0..SOLVE_MAX_STEPS {
+    let middle = (low + high) / 2.0;
+    let error = expected_attempts_with(base, field, middle) - target_mean;
+
+    if error.abs() <= target_mean * SOLVE_TOLERANCE {
+      return Some(middle);
+    }
+
+    if (error > 0.0) == (low_error > 0.0) {
+      low = middle;
+      low_error = error;
+    } else {
+      high = middle;
+    }
+
+    // Out of f32 resolution, the closest representable value is as good as it gets
+    if middle == low && middle == high {
+      break;
+    }
+  }
+
+  return Some((low + high) / 2.0);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::params::default_params;
+
+  #[test]
+  fn solved_value_reproduces_the_target_mean() {
+    let base = default_params();
+    let target = 500.0;
+    let value = solve_for_target(&base, ParamField::MinUpgradeRate, target, (0.05, 0.3)).unwrap();
+
+    let mean = expected_attempts(&generate_rates(&ParamField::MinUpgradeRate.with(&base, value)).unwrap());
+    assert!(((mean - target) / target).abs() <= 1e-3, "solved {value} gives a mean of {mean}");
+  }
+
+  #[test]
+  fn unreachable_target_has_no_solution() {
+    assert_eq!(solve_for_target(&default_params(), ParamField::MinUpgradeRate, 5.0, (0.05, 0.3)), None);
+  }
+
+  #[test]
+  fn easy_set_has_a_lower_mean_than_hard_set() {
+    let easy = EnhancerParams { max_downgrade_rate: 0.1, ..default_params() };
+    let hard = default_params();
+    let results = run_batch(&[(String::from("easy"), easy), (String::from("hard"), hard)], 300, 1);
+
+    assert_eq!(results[0].label, "easy");
+    assert_eq!(results[1].label, "hard");
+    assert!(results[0].mean_attempts < results[1].mean_attempts);
+  }
+}
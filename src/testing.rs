@@ -0,0 +1,74 @@
+// Test-only helpers: seeded batches to test against, and checks of the analytical functions against
+// the simulation. Built for the crate's own tests and enabled for other crates by the `testing`
+// feature. Monte Carlo estimates converge on the exact values as the batch grows, so a large seeded
+// batch should land within a small relative tolerance of the math
+use crate::params::{ generate_rates, EnhanceRules, EnhancerParams };
+use crate::rate::EnhanceRate;
+use crate::simulation::EnhancerSimulation;
+use crate::stats::{ level_statistics, mean_attempts };
+
+// Panics unless `monte_carlo` is within `rel_tol` of `analytical`, relative to the analytical value.
+// An analytical value of 0 is compared absolutely
+pub fn assert_converges(monte_carlo: f64, analytical: f64, rel_tol: f64) {
+  let error = (monte_carlo - analytical).abs();
+  let scale = if analytical == 0.0 { 1.0 } else { analytical.abs() };
+  assert!(
+    error <= rel_tol * scale,
+    "Monte Carlo estimate {monte_carlo} is off from analytical value {analytical} by {:.3}%, tolerance is {:.3}%",
+    error / scale * 100.0,
+    rel_tol * 100.0
+  );
+}
+
+// A batch of `actors` seeded with `seed` and run until every actor is finished, under the default
+// rules
+pub fn completed_batch(rates: &Vec::<EnhanceRate>, actors: i32, seed: u64) -> Vec::<EnhancerSimulation<'_>> {
+  let mut simulations = EnhancerSimulation::create_many_seeded(rates, actors, seed);
+  EnhancerSimulation::run_to_completion(&mut simulations, | _progress | {});
+  return simulations;
+}
+
+// Mean attempts to max of a seeded batch run to completion with `params`' own rates and rules
+pub fn seeded_mean_attempts(params: &EnhancerParams, actors: i32, seed: u64) -> f64 {
+  let rates = generate_rates(params).unwrap();
+  let mut simulations = EnhancerSimulation::create_many_seeded(&rates, actors, seed);
+  EnhancerSimulation::set_rules_many(&mut simulations, EnhanceRules::from_params(params));
+  EnhancerSimulation::run_to_completion(&mut simulations, | _progress | {});
+  return mean_attempts(&simulations);
+}
+
+// Mean attempts to first reach each level over a batch of `actors` seeded with `seed` and run until
+// every actor is finished, the Monte Carlo counterpart of `expected_cost_by_level` with a cost of 1
+// per attempt. Levels nobody reached are NaN
+pub fn monte_carlo_level_means(rates: &Vec::<EnhanceRate>, actors: i32, seed: u64) -> Vec::<f64> {
+  let mut output = vec![f64::NAN; rates.len()];
+  for stats in level_statistics(&completed_batch(rates, actors, seed)) {
+    output[stats.level as usize] = stats.mean;
+  }
+
+  return output;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::analysis::{ expected_attempts, expected_cost_by_level };
+  use crate::params::default_params;
+
+  #[test]
+  fn level_means_converge_on_expected_costs() {
+    let rates = generate_rates(&default_params()).unwrap();
+    let monte_carlo = monte_carlo_level_means(&rates, 5_000, 1);
+    let analytical = expected_cost_by_level(&rates, &vec![1.0; rates.len()]);
+
+    for level in 1..rates.len() {
+      assert_converges(monte_carlo[level], analytical[level], 0.05);
+    }
+  }
+
+  #[test]
+  fn mean_attempts_converges_on_expected_attempts() {
+    let rates = generate_rates(&default_params()).unwrap();
+    assert_converges(mean_attempts(&completed_batch(&rates, 5_000, 1)), expected_attempts(&rates), 0.05);
+  }
+}